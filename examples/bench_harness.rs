@@ -0,0 +1,300 @@
+use bitonic_sorter::sample::SampleSorter;
+use bitonic_sorter::sorter::{
+  Comparator, Iterative, Parallel, Sorter, StdSorter, StdUnstableSorter,
+};
+use bitonic_sorter::utils::generator::{Generator, Shape};
+use bitonic_sorter::SortOrder;
+
+use std::env;
+use std::str::FromStr;
+use std::time::Instant;
+
+// サイズ、要素の型、データの分布を変えて各ソーターを計測し、CSVかJSONで出力する
+// cargo run --release --example bench_harness -- --format json --min-bits 10 --max-bits 16
+
+#[derive(Clone, Copy, PartialEq)]
+enum Format {
+  Csv,
+  Json,
+}
+
+struct Config {
+  format: Format,
+  min_bits: u32,
+  max_bits: u32,
+  trials: usize,
+  warmup: usize,
+}
+
+impl Default for Config {
+  fn default() -> Self {
+    Self {
+      format: Format::Csv,
+      min_bits: 10,
+      max_bits: 16,
+      trials: 5,
+      warmup: 1,
+    }
+  }
+}
+
+fn parse_args() -> Result<Config, String> {
+  let mut config = Config::default();
+  let mut args = env::args().skip(1);
+  while let Some(arg) = args.next() {
+    let value = args
+      .next()
+      .ok_or_else(|| format!("missing value for {}", arg))?;
+    match arg.as_str() {
+      "--format" => {
+        config.format = match value.as_str() {
+          "csv" => Format::Csv,
+          "json" => Format::Json,
+          _ => return Err(format!("unknown format: {}", value)),
+        }
+      }
+      "--min-bits" => config.min_bits = parse_number(&arg, &value)?,
+      "--max-bits" => config.max_bits = parse_number(&arg, &value)?,
+      "--trials" => config.trials = parse_number(&arg, &value)?,
+      "--warmup" => config.warmup = parse_number(&arg, &value)?,
+      _ => return Err(format!("unknown option: {}", arg)),
+    }
+  }
+  if config.trials == 0 {
+    return Err("--trials must be at least 1".to_string());
+  }
+  Ok(config)
+}
+
+fn parse_number<N: FromStr>(name: &str, value: &str) -> Result<N, String> {
+  N::from_str(value).map_err(|_| format!("invalid value for {}: {}", name, value))
+}
+
+fn main() {
+  let config = match parse_args() {
+    Ok(config) => config,
+    Err(e) => {
+      eprintln!("{}", e);
+      eprintln!(
+        "Usage {} [--format csv|json] [--min-bits N] [--max-bits N] [--trials N] [--warmup N]",
+        env::args().next().unwrap()
+      );
+      std::process::exit(1);
+    }
+  };
+
+  let mut results = Vec::new();
+  for bits in config.min_bits..=config.max_bits {
+    let len = 1 << bits;
+    for (distribution, shape) in DISTRIBUTIONS {
+      let distribution = *distribution;
+      let keys: Vec<u32> = Generator::new(SEED).shaped(len, shape);
+      bench_type(&config, "u32", distribution, &keys, &mut results);
+
+      let data: Vec<u64> = keys.iter().map(|&v| u64::from(v) << 16).collect();
+      bench_type(&config, "u64", distribution, &data, &mut results);
+
+      let data: Vec<String> = keys.iter().map(|v| format!("{:010}", v)).collect();
+      bench_type(&config, "String", distribution, &data, &mut results);
+
+      let data: Vec<Student> = keys.iter().map(|&v| Student::new(v)).collect();
+      bench_type(&config, "Student", distribution, &data, &mut results);
+    }
+  }
+
+  match config.format {
+    Format::Csv => print_csv(&results),
+    Format::Json => print_json(&results),
+  }
+}
+
+// 構造体の例。学年、名前の順で比較する
+#[derive(Clone, PartialEq, Eq, PartialOrd, Ord)]
+struct Student {
+  grade: u8,
+  name: String,
+  id: u32,
+}
+
+impl Student {
+  fn new(v: u32) -> Self {
+    Self {
+      grade: (v % 6) as u8,
+      name: format!("student-{}", v),
+      id: v,
+    }
+  }
+}
+
+const DISTRIBUTIONS: &[(&str, Shape)] = &[
+  ("random", Shape::Random),
+  ("sorted", Shape::Sorted),
+  ("reverse", Shape::Reversed),
+  ("organ_pipe", Shape::OrganPipe),
+  ("sawtooth", Shape::Sawtooth { period: 64 }),
+  ("all_equal", Shape::AllEqual),
+  (
+    "few_unique",
+    Shape::Zipf {
+      distinct: 8,
+      exponent: 1.0,
+    },
+  ),
+  ("nearly_sorted", Shape::NearlySorted { swaps: 16 }),
+];
+
+// 毎回同じ入力で計測できるようにシードは固定しておく
+const SEED: u64 = 0;
+
+struct BenchResult {
+  sorter: &'static str,
+  element: &'static str,
+  distribution: &'static str,
+  len: usize,
+  stats: Stats,
+}
+
+fn bench_type<T>(
+  config: &Config,
+  element: &'static str,
+  distribution: &'static str,
+  data: &[T],
+  results: &mut Vec<BenchResult>,
+) where
+  T: Ord + Clone + Send + Sync,
+{
+  let mut push = |sorter: &'static str, samples: Vec<f64>| {
+    results.push(BenchResult {
+      sorter,
+      element,
+      distribution,
+      len: data.len(),
+      stats: Stats::new(samples),
+    })
+  };
+  push("third", run_trials(config, &Comparator, data));
+  push("fourth", run_trials(config, &Parallel, data));
+  push("sample", run_trials(config, &SampleSorter::new(), data));
+  push("iterative", run_trials(config, &Iterative, data));
+  push("std_sort", run_trials(config, &StdSorter, data));
+  push(
+    "std_sort_unstable",
+    run_trials(config, &StdUnstableSorter, data),
+  );
+}
+
+// warmup回だけ捨てて実行してから、trials回の実行時間をナノ秒で返す
+fn run_trials<T, S>(config: &Config, sorter: &S, data: &[T]) -> Vec<f64>
+where
+  T: Ord + Clone,
+  S: Sorter<T>,
+{
+  for _ in 0..config.warmup {
+    timed(sorter, data);
+  }
+  (0..config.trials).map(|_| timed(sorter, data)).collect()
+}
+
+fn timed<T, S>(sorter: &S, data: &[T]) -> f64
+where
+  T: Ord + Clone,
+  S: Sorter<T>,
+{
+  let mut x = data.to_vec();
+  let start = Instant::now();
+  sorter
+    .sort(&mut x, &SortOrder::Ascending)
+    .expect("Failed to sort: ");
+  let dur = start.elapsed();
+  assert!(x.windows(2).all(|pair| pair[0] <= pair[1]));
+  dur.subsec_nanos() as f64 + dur.as_secs() as f64 * 1e9_f64
+}
+
+struct Stats {
+  trials: usize,
+  median: f64,
+  mean: f64,
+  stddev: f64,
+  min: f64,
+}
+
+impl Stats {
+  fn new(mut samples: Vec<f64>) -> Self {
+    samples.sort_by(|a, b| a.partial_cmp(b).unwrap());
+    let n = samples.len();
+    // 奇数個なら同じ要素を2回足すことになる
+    let median = (samples[(n - 1) / 2] + samples[n / 2]) / 2.0;
+    let mean = samples.iter().sum::<f64>() / n as f64;
+    // 標本標準偏差。1回しか計測していない時は0にする
+    let stddev = if n > 1 {
+      let var = samples.iter().map(|s| (s - mean).powi(2)).sum::<f64>() / (n - 1) as f64;
+      var.sqrt()
+    } else {
+      0.0
+    };
+    Self {
+      trials: n,
+      median,
+      mean,
+      stddev,
+      min: samples[0],
+    }
+  }
+}
+
+fn print_csv(results: &[BenchResult]) {
+  println!("sorter,element,distribution,len,trials,median_ns,mean_ns,stddev_ns,min_ns");
+  for r in results {
+    println!(
+      "{},{},{},{},{},{:.0},{:.0},{:.0},{:.0}",
+      r.sorter,
+      r.element,
+      r.distribution,
+      r.len,
+      r.stats.trials,
+      r.stats.median,
+      r.stats.mean,
+      r.stats.stddev,
+      r.stats.min
+    );
+  }
+}
+
+fn print_json(results: &[BenchResult]) {
+  println!("[");
+  for (i, r) in results.iter().enumerate() {
+    println!(
+      "  {{\"sorter\": \"{}\", \"element\": \"{}\", \"distribution\": \"{}\", \"len\": {}, \"trials\": {}, \"median_ns\": {:.0}, \"mean_ns\": {:.0}, \"stddev_ns\": {:.0}, \"min_ns\": {:.0}}}{}",
+      r.sorter,
+      r.element,
+      r.distribution,
+      r.len,
+      r.stats.trials,
+      r.stats.median,
+      r.stats.mean,
+      r.stats.stddev,
+      r.stats.min,
+      if i + 1 < results.len() { "," } else { "" }
+    );
+  }
+  println!("]");
+}
+
+#[cfg(test)]
+mod tests {
+  use super::Stats;
+
+  #[test]
+  fn stats_median_and_stddev() {
+    let stats = Stats::new(vec![4.0, 1.0, 3.0, 2.0]);
+    assert_eq!(stats.trials, 4);
+    assert_eq!(stats.median, 2.5);
+    assert_eq!(stats.mean, 2.5);
+    assert_eq!(stats.min, 1.0);
+    assert!((stats.stddev - 1.2909944).abs() < 1e-6);
+
+    let stats = Stats::new(vec![7.0]);
+    assert_eq!(stats.median, 7.0);
+    assert_eq!(stats.stddev, 0.0);
+  }
+}
@@ -1,24 +1,59 @@
 use num_cpus;
 
-use bitonic_sorter::fourth::sort as par_sort;
-use bitonic_sorter::third::sort as seq_sort;
+use bitonic_sorter::fourth::ParSorter;
+use bitonic_sorter::sample::SampleSorter;
+use bitonic_sorter::sorter::{Comparator, Iterative, Parallel, Sorter, Vectorized};
 use bitonic_sorter::utils::{is_sorted_ascending, new_u32_vec};
-use bitonic_sorter::SortOrder;
+use bitonic_sorter::{simd, SortError, SortOrder};
 
 use std::str::FromStr;
 use std::time::Instant;
 use std::{env, f64};
 
 fn main() {
-  if let Some(n) = env::args().nth(1) {
-    let bits = u32::from_str(&n).expect("error parsing argument");
-    run_sorts(bits);
-  } else {
-    eprintln!(
-      "Usage {} <number of elements is bits>",
-      env::args().nth(0).unwrap()
+  match env::args().nth(1) {
+    Some(ref mode) if mode == "iterative" => {
+      let max_bits = env::args()
+        .nth(2)
+        .map(|n| u32::from_str(&n).expect("error parsing argument"))
+        .unwrap_or(26);
+      run_iterative_sweep(10, max_bits);
+    }
+    Some(ref mode) if mode == "threshold" => {
+      let bits = env::args()
+        .nth(2)
+        .map(|n| u32::from_str(&n).expect("error parsing argument"))
+        .unwrap_or(20);
+      run_threshold_sweep(bits);
+    }
+    Some(n) => {
+      let bits = u32::from_str(&n).expect("error parsing argument");
+      run_sorts(bits);
+    }
+    None => {
+      let program = env::args().next().unwrap();
+      eprintln!(
+        "Usage {} <number of elements is bits>\n      {} iterative [max bits]\n      {} threshold [bits]",
+        program,
+        program,
+        program
+      );
+      std::process::exit(1);
+    }
+  }
+}
+
+// 再帰版のthird::sortと反復版のiterative::sortを2^min_bitsから2^max_bitsまで比較する
+fn run_iterative_sweep(min_bits: u32, max_bits: u32) {
+  for bits in min_bits..=max_bits {
+    let len = 2.0_f64.powi(bits as i32) as usize;
+    let seq_duration = timed_sort(&Comparator, len, "seq_sort");
+    let iter_duration = timed_sort(&Iterative, len, "iter_sort");
+    println!(
+      "2^{}: iterative speed up: {:.2}x",
+      bits,
+      seq_duration / iter_duration
     );
-    std::process::exit(1);
   }
 }
 
@@ -37,21 +72,72 @@ fn run_sorts(bits: u32) {
     num_cpus::get(),
   );
 
-  let seq_duration = timed_sort(&seq_sort, len, "seq_sort");
+  let seq_duration = timed_sort(&Comparator, len, "seq_sort");
 
-  let par_duration = timed_sort(&par_sort, len, "par_sort");
+  let par_duration = timed_sort(&Parallel, len, "par_sort");
 
   println!("speed up: {:.2}x", seq_duration / par_duration);
+
+  // 同じParSorterで、マージの段のcompare_and_swapをチャンクに分けて並列にした時と逐次の時の比較
+  let chunked_duration = timed_sort(&ParSorter::new(), len, "par_sort(chunked merge)");
+  let serial_merge_duration = timed_sort(
+    &ParSorter::new().chunked_merge(false),
+    len,
+    "par_sort(serial merge)",
+  );
+
+  println!(
+    "chunked merge speed up: {:.2}x",
+    serial_merge_duration / chunked_duration
+  );
+
+  // bitonicの再帰で分けるfourthと、値の範囲でバケットに分けるサンプルソートの比較
+  let sample_duration = timed_sort(&SampleSorter::new(), len, "sample_sort");
+
+  println!(
+    "sample speed up: {:.2}x (vs par_sort: {:.2}x)",
+    seq_duration / sample_duration,
+    par_duration / sample_duration
+  );
+
+  // simd::sort_primitiveと、同じネットワークをスカラーのmin/maxでたどるsort_primitive_scalarの比較
+  let simd_duration = timed_sort(&Vectorized, len, "simd_sort");
+  let scalar_duration = timed(len, "scalar_sort", |x| {
+    simd::sort_primitive_scalar(x, &SortOrder::Ascending)
+  });
+
+  println!("simd speed up: {:.2}x", scalar_duration / simd_duration);
+}
+
+// ParSorterの並列化の閾値を2^4から2^(bits - 1)まで変えて、マシンごとに最適な値を探す
+fn run_threshold_sweep(bits: u32) {
+  let len = 2.0_f64.powi(bits as i32) as usize;
+  let seq_duration = timed_sort(&Comparator, len, "seq_sort");
+  for threshold_bits in 4..bits {
+    let threshold = 1 << threshold_bits;
+    let sorter = ParSorter::new().threshold(threshold);
+    let name = format!("par_sort(threshold: {})", threshold);
+    let par_duration = timed_sort(&sorter, len, &name);
+    println!(
+      "threshold {}: speed up: {:.2}x",
+      threshold,
+      seq_duration / par_duration
+    );
+  }
+}
+
+fn timed_sort<S: Sorter<u32>>(sorter: &S, len: usize, name: &str) -> f64 {
+  timed(len, name, |x| sorter.sort(x, &SortOrder::Ascending))
 }
 
-fn timed_sort<F>(sorter: &F, len: usize, name: &str) -> f64
+fn timed<F>(len: usize, name: &str, sort: F) -> f64
 where
-  F: Fn(&mut [u32], &SortOrder) -> Result<(), String>,
+  F: FnOnce(&mut [u32]) -> Result<(), SortError>,
 {
   let mut x = new_u32_vec(len);
 
   let start = Instant::now();
-  sorter(&mut x, &SortOrder::Ascending).expect("Failed to sort: ");
+  sort(&mut x).expect("Failed to sort: ");
   let dur = start.elapsed();
 
   let nano_secs = dur.subsec_nanos() as f64 + dur.as_secs() as f64 * 1e9_f64;
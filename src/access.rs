@@ -0,0 +1,217 @@
+use std::cmp::Ordering;
+use std::collections::VecDeque;
+
+// 添字で要素を比較・交換できるコンテナ。third::sort_byとfourth::sort_byはこれを実装した型を
+// 要素を取り出さずにその場でソートできる
+pub trait RandomAccessMut {
+  type Item;
+
+  fn len(&self) -> usize;
+  fn swap(&mut self, a: usize, b: usize);
+  fn compare<F>(&self, a: usize, b: usize, comparator: &F) -> Ordering
+  where
+    F: Fn(&Self::Item, &Self::Item) -> Ordering;
+
+  fn is_empty(&self) -> bool {
+    self.len() == 0
+  }
+
+  // 要素が連続したメモリに並んでいればスライスとして返す。
+  // スライスを返せる型はスライス用の(fourthでは並列の)実装でソートする
+  fn as_contiguous_mut(&mut self) -> Option<&mut [Self::Item]> {
+    None
+  }
+}
+
+impl<T> RandomAccessMut for [T] {
+  type Item = T;
+
+  fn len(&self) -> usize {
+    <[T]>::len(self)
+  }
+  fn swap(&mut self, a: usize, b: usize) {
+    <[T]>::swap(self, a, b)
+  }
+  fn compare<F>(&self, a: usize, b: usize, comparator: &F) -> Ordering
+  where
+    F: Fn(&T, &T) -> Ordering,
+  {
+    comparator(&self[a], &self[b])
+  }
+  fn as_contiguous_mut(&mut self) -> Option<&mut [T]> {
+    Some(self)
+  }
+}
+
+impl<T> RandomAccessMut for Vec<T> {
+  type Item = T;
+
+  fn len(&self) -> usize {
+    Vec::len(self)
+  }
+  fn swap(&mut self, a: usize, b: usize) {
+    self.as_mut_slice().swap(a, b)
+  }
+  fn compare<F>(&self, a: usize, b: usize, comparator: &F) -> Ordering
+  where
+    F: Fn(&T, &T) -> Ordering,
+  {
+    comparator(&self[a], &self[b])
+  }
+  fn as_contiguous_mut(&mut self) -> Option<&mut [T]> {
+    Some(self.as_mut_slice())
+  }
+}
+
+// &mut [T]などの可変参照も、参照先と同じように扱う
+impl<C: RandomAccessMut + ?Sized> RandomAccessMut for &mut C {
+  type Item = C::Item;
+
+  fn len(&self) -> usize {
+    (**self).len()
+  }
+  fn swap(&mut self, a: usize, b: usize) {
+    (**self).swap(a, b)
+  }
+  fn compare<F>(&self, a: usize, b: usize, comparator: &F) -> Ordering
+  where
+    F: Fn(&C::Item, &C::Item) -> Ordering,
+  {
+    (**self).compare(a, b, comparator)
+  }
+  fn as_contiguous_mut(&mut self) -> Option<&mut [C::Item]> {
+    (**self).as_contiguous_mut()
+  }
+}
+
+// リングバッファは先頭が途中にあるので、make_contiguousで詰め直してからスライスとしてソートする。
+// 並び順は変わらないので、ソートの結果には影響しない
+impl<T> RandomAccessMut for VecDeque<T> {
+  type Item = T;
+
+  fn len(&self) -> usize {
+    VecDeque::len(self)
+  }
+  fn swap(&mut self, a: usize, b: usize) {
+    VecDeque::swap(self, a, b)
+  }
+  fn compare<F>(&self, a: usize, b: usize, comparator: &F) -> Ordering
+  where
+    F: Fn(&T, &T) -> Ordering,
+  {
+    comparator(&self[a], &self[b])
+  }
+  fn as_contiguous_mut(&mut self) -> Option<&mut [T]> {
+    Some(self.make_contiguous())
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::RandomAccessMut;
+  use crate::utils::new_u32_vec;
+  use crate::SortOrder::*;
+  use crate::{fourth, third};
+  use std::cmp::Ordering;
+  use std::collections::VecDeque;
+
+  // スライスを返せないコンテナの例。先頭がheadにあるリングバッファ
+  struct Ring {
+    buf: Vec<u32>,
+    head: usize,
+  }
+
+  impl Ring {
+    fn new(values: &[u32], head: usize) -> Self {
+      let head = head.checked_rem(values.len()).unwrap_or(0);
+      let mut buf = values.to_vec();
+      buf.rotate_right(head);
+      Self { buf, head }
+    }
+
+    fn index(&self, i: usize) -> usize {
+      (self.head + i) % self.buf.len()
+    }
+
+    fn to_vec(&self) -> Vec<u32> {
+      (0..self.buf.len())
+        .map(|i| self.buf[self.index(i)])
+        .collect()
+    }
+  }
+
+  impl RandomAccessMut for Ring {
+    type Item = u32;
+
+    fn len(&self) -> usize {
+      self.buf.len()
+    }
+    fn swap(&mut self, a: usize, b: usize) {
+      let (a, b) = (self.index(a), self.index(b));
+      self.buf.swap(a, b)
+    }
+    fn compare<F>(&self, a: usize, b: usize, comparator: &F) -> Ordering
+    where
+      F: Fn(&u32, &u32) -> Ordering,
+    {
+      comparator(&self.buf[self.index(a)], &self.buf[self.index(b)])
+    }
+  }
+
+  #[test]
+  fn sort_non_contiguous_container() {
+    for len in (0..=300).chain(vec![1024, 4097]) {
+      let x = new_u32_vec(len);
+      let mut expected = x.clone();
+      expected.sort();
+
+      let mut ring = Ring::new(&x, len / 3);
+      assert_eq!(ring.to_vec(), x);
+      assert_eq!(third::sort(&mut ring, &Ascending), Ok(()));
+      assert_eq!(ring.to_vec(), expected);
+
+      let mut ring = Ring::new(&x, len / 2);
+      assert_eq!(fourth::sort_by(&mut ring, &|a, b| b.cmp(a)), Ok(()));
+      expected.reverse();
+      assert_eq!(ring.to_vec(), expected);
+    }
+  }
+
+  #[test]
+  fn sort_vec_deque() {
+    let x = new_u32_vec(1000);
+    let mut expected = x.clone();
+    expected.sort();
+
+    // 先頭と末尾の両方から積んで、内部のバッファで折り返した状態にする
+    let mut deque: VecDeque<u32> = VecDeque::new();
+    for (i, &v) in x.iter().enumerate() {
+      if i % 2 == 0 {
+        deque.push_back(v);
+      } else {
+        deque.push_front(v);
+      }
+    }
+    let mut y = deque.clone();
+    assert_eq!(third::sort(&mut y, &Ascending), Ok(()));
+    assert!(y.iter().eq(expected.iter()));
+
+    let mut y = deque;
+    assert_eq!(fourth::sort(&mut y, &Descending), Ok(()));
+    assert!(y.iter().eq(expected.iter().rev()));
+  }
+
+  #[test]
+  fn vec_deque_access() {
+    let mut x: VecDeque<u32> = VecDeque::with_capacity(4);
+    x.push_back(3);
+    x.push_back(4);
+    x.push_front(2);
+    x.push_front(1);
+    assert_eq!(RandomAccessMut::len(&x), 4);
+    assert_eq!(x.compare(0, 3, &|a, b| a.cmp(b)), Ordering::Less);
+    RandomAccessMut::swap(&mut x, 0, 3);
+    assert_eq!(x, vec![4, 2, 3, 1]);
+    assert_eq!(x.as_contiguous_mut(), Some(&mut [4, 2, 3, 1][..]));
+  }
+}
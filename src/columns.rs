@@ -0,0 +1,235 @@
+use super::access::RandomAccessMut;
+use super::utils::permutation_swaps;
+use super::{fourth, third, SortError, SortOrder};
+use rayon::prelude::*;
+use std::cmp::Ordering;
+
+// 列ごとに別のVecで持っているデータを、キーの列と同じ順番に並べ替えるための列。
+// RandomAccessMutのうち長さと入れ替えだけを取り出したもので、&mut dynにして型の違う列を並べられる。
+// RandomAccessMutを実装した型は全て列として使える
+pub trait PermuteColumn {
+  fn len(&self) -> usize;
+  fn swap(&mut self, a: usize, b: usize);
+
+  fn is_empty(&self) -> bool {
+    self.len() == 0
+  }
+}
+
+impl<C: RandomAccessMut + ?Sized> PermuteColumn for C {
+  fn len(&self) -> usize {
+    RandomAccessMut::len(self)
+  }
+  fn swap(&mut self, a: usize, b: usize) {
+    RandomAccessMut::swap(self, a, b)
+  }
+}
+
+pub fn sort<K: Ord>(
+  keys: &mut [K],
+  order: &SortOrder,
+  columns: &mut [&mut dyn PermuteColumn],
+) -> Result<(), SortError> {
+  match *order {
+    SortOrder::Ascending => sort_by(keys, &|a, b| a.cmp(b), columns),
+    SortOrder::Descending => sort_by(keys, &|a, b| b.cmp(a), columns),
+  }
+}
+
+// キーの列をthird::sort_byでソートし、同じ並べ替えをcolumnsの全ての列に適用する
+// キーが等しい行は元の順番のままになる
+pub fn sort_by<K, F>(
+  keys: &mut [K],
+  comparator: &F,
+  columns: &mut [&mut dyn PermuteColumn],
+) -> Result<(), SortError>
+where
+  F: Fn(&K, &K) -> Ordering,
+{
+  // &mut [K]はRandomAccessMutとPermuteColumnの両方のlenを持つので、スライスのlenを明示する
+  check_len(<[K]>::len(keys), columns.iter().map(|c| c.len()))?;
+  let swaps = permutation_swaps(&third::argsort_by(keys, comparator)?);
+  apply_swaps(keys, &swaps);
+  for column in columns.iter_mut() {
+    apply_swaps(&mut **column, &swaps);
+  }
+  Ok(())
+}
+
+pub fn par_sort<K: Ord + Sync + Send>(
+  keys: &mut [K],
+  order: &SortOrder,
+  columns: &mut [&mut (dyn PermuteColumn + Send)],
+) -> Result<(), SortError> {
+  match *order {
+    SortOrder::Ascending => par_sort_by(keys, &|a, b| a.cmp(b), columns),
+    SortOrder::Descending => par_sort_by(keys, &|a, b| b.cmp(a), columns),
+  }
+}
+
+// sort_byの並列版。キーの列はfourth::sort_byでソートし、各列への適用も列ごとに並列に行う
+pub fn par_sort_by<K, F>(
+  keys: &mut [K],
+  comparator: &F,
+  columns: &mut [&mut (dyn PermuteColumn + Send)],
+) -> Result<(), SortError>
+where
+  K: Sync + Send,
+  F: Sync + Fn(&K, &K) -> Ordering,
+{
+  check_len(<[K]>::len(keys), columns.iter().map(|c| c.len()))?;
+  let swaps = permutation_swaps(&fourth::argsort_by(keys, comparator)?);
+  rayon::join(
+    || apply_swaps(keys, &swaps),
+    || {
+      columns
+        .par_iter_mut()
+        .for_each(|column| apply_swaps(&mut **column, &swaps))
+    },
+  );
+  Ok(())
+}
+
+fn check_len<I: Iterator<Item = usize>>(expected: usize, lens: I) -> Result<(), SortError> {
+  for actual in lens {
+    if actual != expected {
+      return Err(SortError::LengthMismatch { expected, actual });
+    }
+  }
+  Ok(())
+}
+
+// utils::permutation_swapsで求めたswapの列を適用する
+fn apply_swaps<C: PermuteColumn + ?Sized>(column: &mut C, swaps: &[usize]) {
+  for (i, &index) in swaps.iter().enumerate() {
+    column.swap(i, index);
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::{par_sort, par_sort_by, sort, sort_by, PermuteColumn};
+  use crate::primitive::toyvec::ToyVec;
+  use crate::utils::new_u32_vec;
+  use crate::SortError;
+  use crate::SortOrder::*;
+  use std::collections::VecDeque;
+
+  #[test]
+  fn sort_columns_by_response_time() {
+    let mut response_time = vec![30, 10, 20, 10];
+    let mut path = vec!["/c", "/a", "/b", "/d"];
+    let mut status = vec![500, 200, 404, 200];
+
+    assert_eq!(
+      sort(
+        &mut response_time,
+        &Ascending,
+        &mut [&mut path, &mut status]
+      ),
+      Ok(())
+    );
+
+    assert_eq!(response_time, vec![10, 10, 20, 30]);
+    assert_eq!(path, vec!["/a", "/d", "/b", "/c"]);
+    assert_eq!(status, vec![200, 200, 404, 500]);
+  }
+
+  #[test]
+  fn sort_columns_slices() {
+    let mut keys = vec![3, 1, 2];
+    let mut names = vec!["c".to_string(), "a".to_string(), "b".to_string()];
+    let mut flags = [true, false, true];
+    {
+      let mut names: &mut [String] = &mut names;
+      let mut flags: &mut [bool] = &mut flags;
+      assert_eq!(
+        sort_by(&mut keys, &|a, b| b.cmp(a), &mut [&mut names, &mut flags]),
+        Ok(())
+      );
+    }
+    assert_eq!(keys, vec![3, 2, 1]);
+    assert_eq!(names, vec!["c", "b", "a"]);
+    assert_eq!(flags, [true, true, false]);
+  }
+
+  #[test]
+  fn sort_columns_of_other_containers() {
+    let mut keys = vec![2, 3, 1];
+    let mut queue: VecDeque<char> = VecDeque::new();
+    queue.push_back('b');
+    queue.push_back('c');
+    queue.push_front('a');
+    let mut toy = ToyVec::new();
+    for v in &["two", "three", "one"] {
+      toy.push(v.to_string());
+    }
+    // queueは[a, b, c]なので、キーと同じ順に並べると[c, a, b]になる
+    assert_eq!(
+      sort(&mut keys, &Ascending, &mut [&mut queue, &mut toy]),
+      Ok(())
+    );
+    assert_eq!(keys, vec![1, 2, 3]);
+    assert_eq!(queue, vec!['c', 'a', 'b']);
+    let names: Vec<&String> = toy.iter().collect();
+    assert_eq!(names, vec!["one", "two", "three"]);
+  }
+
+  #[test]
+  fn sort_columns_length_mismatch() {
+    let mut keys = vec![3, 1, 2];
+    let mut values = vec![1, 2];
+    assert_eq!(
+      sort(&mut keys, &Ascending, &mut [&mut values]),
+      Err(SortError::LengthMismatch {
+        expected: 3,
+        actual: 2
+      })
+    );
+    assert_eq!(keys, vec![3, 1, 2]);
+    assert!(!PermuteColumn::is_empty(&values));
+  }
+
+  #[test]
+  fn par_sort_columns_same_as_sort() {
+    for &len in &[0, 1, 1000, 10_000] {
+      let keys: Vec<u32> = new_u32_vec(len).into_iter().map(|v| v % 100).collect();
+      let rows: Vec<usize> = (0..len).collect();
+      let labels: Vec<String> = rows.iter().map(|i| i.to_string()).collect();
+
+      let (mut keys1, mut rows1, mut labels1) = (keys.clone(), rows.clone(), labels.clone());
+      assert_eq!(
+        sort(&mut keys1, &Descending, &mut [&mut rows1, &mut labels1]),
+        Ok(())
+      );
+      let (mut keys2, mut rows2, mut labels2) = (keys.clone(), rows, labels);
+      assert_eq!(
+        par_sort(&mut keys2, &Descending, &mut [&mut rows2, &mut labels2]),
+        Ok(())
+      );
+      assert_eq!(keys1, keys2);
+      assert_eq!(rows1, rows2);
+      assert_eq!(labels1, labels2);
+
+      // 元の位置の列でキーを引くと並べ替え後のキーと一致する
+      for (row, key) in rows1.iter().zip(keys1.iter()) {
+        assert_eq!(keys[*row], *key);
+      }
+    }
+  }
+
+  #[test]
+  fn par_sort_columns_by_multi_key() {
+    let mut keys = vec![(2, "b"), (1, "z"), (2, "a"), (1, "y")];
+    let mut ids = vec![0, 1, 2, 3];
+    assert_eq!(
+      par_sort_by(
+        &mut keys,
+        &|a, b| a.0.cmp(&b.0).then_with(|| b.1.cmp(a.1)),
+        &mut [&mut ids]
+      ),
+      Ok(())
+    );
+    assert_eq!(ids, vec![1, 3, 0, 2]);
+  }
+}
@@ -0,0 +1,201 @@
+use super::SortOrder;
+use std::cmp::Ordering;
+
+// sort_byに渡す比較関数を組み立てるための関数。
+// キーごとに向きを決めて、thenでつなげると複数のキーで並べる比較関数になる
+//
+//   by_key(&Ascending, |s: &Student| s.age)
+//     .then(by_field(&Descending, |s: &Student| &s.last_name))
+
+// Optionのキーで、Noneを並べる位置。SortOrderに関係なく先頭か末尾にまとめる
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Nulls {
+  First,
+  Last,
+}
+
+// key_fnで取り出したキーをorderの向きで比較する。キーは比較のたびに計算する
+pub fn by_key<T, K, F>(order: &SortOrder, key_fn: F) -> impl Fn(&T, &T) -> Ordering
+where
+  K: Ord,
+  F: Fn(&T) -> K,
+{
+  let order = *order;
+  move |a, b| order.apply(key_fn(a).cmp(&key_fn(b)))
+}
+
+// by_keyのキーを参照で取り出す版。Stringなどのフィールドをcloneせずに比較できる
+pub fn by_field<T, K, F>(order: &SortOrder, field: F) -> impl Fn(&T, &T) -> Ordering
+where
+  K: Ord + ?Sized,
+  F: Fn(&T) -> &K,
+{
+  let order = *order;
+  move |a, b| order.apply(field(a).cmp(field(b)))
+}
+
+// Optionのキーで比較する。Some同士はorderの向きで比較し、Noneはnullsの位置に並べる
+pub fn by_optional_key<T, K, F>(
+  order: &SortOrder,
+  nulls: Nulls,
+  key_fn: F,
+) -> impl Fn(&T, &T) -> Ordering
+where
+  K: Ord,
+  F: Fn(&T) -> Option<K>,
+{
+  let order = *order;
+  move |a, b| match (key_fn(a), key_fn(b)) {
+    (Some(a), Some(b)) => order.apply(a.cmp(&b)),
+    (None, None) => Ordering::Equal,
+    (None, Some(_)) if nulls == Nulls::First => Ordering::Less,
+    (None, Some(_)) => Ordering::Greater,
+    (Some(_), None) if nulls == Nulls::First => Ordering::Greater,
+    (Some(_), None) => Ordering::Less,
+  }
+}
+
+// 比較関数をつなげるためのメソッド。Fn(&T, &T) -> Orderingを実装した全ての型で使える。
+// つなげた比較関数は元の比較関数が両方Syncならfourthにも渡せる
+pub trait ComparatorExt<T>: Fn(&T, &T) -> Ordering + Sized {
+  // selfで等しい時だけnextで比較する
+  fn then<G>(self, next: G) -> impl Fn(&T, &T) -> Ordering
+  where
+    G: Fn(&T, &T) -> Ordering,
+  {
+    move |a, b| self(a, b).then_with(|| next(a, b))
+  }
+
+  // then(by_key(order, key_fn))の省略形
+  fn then_with<K, F>(self, order: &SortOrder, key_fn: F) -> impl Fn(&T, &T) -> Ordering
+  where
+    K: Ord,
+    F: Fn(&T) -> K,
+  {
+    self.then(by_key(order, key_fn))
+  }
+
+  // 比較の向きを全て逆にする
+  fn reversed(self) -> impl Fn(&T, &T) -> Ordering {
+    move |a, b| self(b, a)
+  }
+}
+
+impl<T, F: Fn(&T, &T) -> Ordering> ComparatorExt<T> for F {}
+
+#[cfg(test)]
+mod tests {
+  use super::{by_field, by_key, by_optional_key, ComparatorExt, Nulls};
+  use crate::SortOrder::*;
+  use crate::{fourth, third};
+  use std::cmp::Ordering::*;
+
+  #[derive(Debug, Clone, PartialEq)]
+  struct Row {
+    name: &'static str,
+    grade: u8,
+    score: Option<u32>,
+  }
+
+  fn rows() -> Vec<Row> {
+    vec![
+      Row {
+        name: "taro",
+        grade: 2,
+        score: Some(80),
+      },
+      Row {
+        name: "hanako",
+        grade: 1,
+        score: None,
+      },
+      Row {
+        name: "kyoko",
+        grade: 2,
+        score: None,
+      },
+      Row {
+        name: "ryosuke",
+        grade: 1,
+        score: Some(95),
+      },
+      Row {
+        name: "jiro",
+        grade: 2,
+        score: Some(95),
+      },
+      Row {
+        name: "saburo",
+        grade: 1,
+        score: Some(60),
+      },
+    ]
+  }
+
+  fn names(x: &[Row]) -> Vec<&'static str> {
+    x.iter().map(|r| r.name).collect()
+  }
+
+  #[test]
+  fn apply_order() {
+    assert_eq!(Ascending.apply(Less), Less);
+    assert_eq!(Descending.apply(Less), Greater);
+    assert_eq!(Descending.apply(Equal), Equal);
+  }
+
+  #[test]
+  fn by_key_with_direction() {
+    let mut x = rows();
+    assert_eq!(
+      third::sort_by(&mut x, &by_field(&Descending, |r: &Row| r.name)),
+      Ok(())
+    );
+    assert_eq!(
+      names(&x),
+      vec!["taro", "saburo", "ryosuke", "kyoko", "jiro", "hanako"]
+    );
+
+    let cmp = by_key(&Ascending, |r: &Row| r.grade);
+    assert_eq!(cmp(&x[0], &x[1]), Greater);
+    assert_eq!(cmp(&x[0], &x[3]), Equal);
+  }
+
+  #[test]
+  fn multi_key_with_independent_directions() {
+    // 学年の昇順、点数の降順(点数なしは末尾)、名前の昇順
+    let cmp = by_key(&Ascending, |r: &Row| r.grade)
+      .then(by_optional_key(&Descending, Nulls::Last, |r: &Row| r.score))
+      .then(by_field(&Ascending, |r: &Row| r.name));
+    let mut x = rows();
+    assert_eq!(fourth::sort_by(&mut x, &cmp), Ok(()));
+    assert_eq!(
+      names(&x),
+      vec!["ryosuke", "saburo", "hanako", "jiro", "taro", "kyoko"]
+    );
+
+    let mut y = rows();
+    assert_eq!(third::sort_by(&mut y, &cmp.reversed()), Ok(()));
+    x.reverse();
+    assert_eq!(y, x);
+  }
+
+  #[test]
+  fn nulls_position_ignores_direction() {
+    let mut x = rows();
+    let cmp = by_optional_key(&Ascending, Nulls::First, |r: &Row| r.score)
+      .then_with(&Descending, |r: &Row| r.name);
+    assert_eq!(third::sort_by(&mut x, &cmp), Ok(()));
+    assert_eq!(
+      names(&x),
+      vec!["kyoko", "hanako", "saburo", "taro", "ryosuke", "jiro"]
+    );
+
+    let cmp = by_optional_key(&Descending, Nulls::First, |r: &Row| r.score)
+      .then_with(&Ascending, |r: &Row| r.name);
+    assert_eq!(third::sort_by(&mut x, &cmp), Ok(()));
+    assert_eq!(
+      names(&x),
+      vec!["hanako", "kyoko", "jiro", "ryosuke", "taro", "saburo"]
+    );
+  }
+}
@@ -0,0 +1,506 @@
+use super::fourth;
+use std::cmp::Ordering;
+use std::env;
+use std::fs::{self, File};
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::path::{Path, PathBuf};
+use std::process;
+use std::sync::atomic::{AtomicUsize, Ordering as AtomicOrdering};
+
+const DEFAULT_MEMORY_BUDGET: usize = 64 * 1024 * 1024;
+
+// 一度のマージで同時に開く一時ファイルの数の既定値
+const DEFAULT_MAX_OPEN_RUNS: usize = 64;
+
+// runを作る時にファイルを読むバッファの大きさ。memory_budgetとは別に使う
+const READ_BUF_SIZE: usize = 8 * 1024;
+
+// 一時ファイルの名前が同じプロセス内のソート同士で重ならないようにする
+static SORT_ID: AtomicUsize = AtomicUsize::new(0);
+
+// ファイルに固定長で読み書きできるレコード
+pub trait Record: Sized + Send {
+  const SIZE: usize;
+  fn read_bytes(buf: &[u8]) -> Self;
+  fn write_bytes(&self, buf: &mut [u8]);
+}
+
+macro_rules! impl_record {
+  ($($t:ty),*) => {
+    $(impl Record for $t {
+      const SIZE: usize = std::mem::size_of::<$t>();
+
+      fn read_bytes(buf: &[u8]) -> Self {
+        let mut bytes = [0; std::mem::size_of::<$t>()];
+        bytes.copy_from_slice(buf);
+        <$t>::from_le_bytes(bytes)
+      }
+
+      fn write_bytes(&self, buf: &mut [u8]) {
+        buf.copy_from_slice(&self.to_le_bytes());
+      }
+    })*
+  };
+}
+
+impl_record!(u32, i32, u64, i64);
+
+// メモリに載らない大きさのファイルをソートする
+// memory_budgetに収まる2のべき乗個のレコードずつfourth::sort_byでソートして一時ファイルに書き出し、
+// 最後に一時ファイルをk-wayマージして出力ファイルを作る。一時ファイルがmax_open_runsより多い時は、
+// max_open_runs個ずつ中間の一時ファイルにマージすることを繰り返して数を減らしてから出力する
+pub struct ExternalSorter {
+  memory_budget: usize,
+  max_open_runs: usize,
+  temp_dir: PathBuf,
+}
+
+impl Default for ExternalSorter {
+  fn default() -> Self {
+    Self {
+      memory_budget: DEFAULT_MEMORY_BUDGET,
+      max_open_runs: DEFAULT_MAX_OPEN_RUNS,
+      temp_dir: env::temp_dir(),
+    }
+  }
+}
+
+impl ExternalSorter {
+  pub fn new() -> Self {
+    Self::default()
+  }
+
+  // ソート中にメモリに置くレコードのバイト数。runを作る時はrun_len個のレコードの大きさで、
+  // ファイルは小さなバッファから1レコードずつ読み込む。マージする時は読み書きのバッファの合計になる。
+  // 1レコードより小さくても1レコードずつは読む
+  pub fn memory_budget(mut self, bytes: usize) -> Self {
+    self.memory_budget = bytes;
+    self
+  }
+
+  // 一度のマージで同時に開く一時ファイルの数。ファイルディスクリプタの上限より小さくする。
+  // 2より小さい値は2として扱う
+  pub fn max_open_runs(mut self, runs: usize) -> Self {
+    self.max_open_runs = runs;
+    self
+  }
+
+  pub fn temp_dir<P: AsRef<Path>>(mut self, dir: P) -> Self {
+    self.temp_dir = dir.as_ref().to_path_buf();
+    self
+  }
+
+  // 一度にソートするレコード数。memory_budgetに収まる最大の2のべき乗
+  pub fn run_len<R: Record>(&self) -> usize {
+    let records = (self.memory_budget / R::SIZE).max(1);
+    if records.is_power_of_two() {
+      records
+    } else {
+      records.next_power_of_two() / 2
+    }
+  }
+
+  pub fn sort_file<R, F, P, Q>(&self, input: P, output: Q, comparator: &F) -> io::Result<usize>
+  where
+    R: Record,
+    F: Sync + Fn(&R, &R) -> Ordering,
+    P: AsRef<Path>,
+    Q: AsRef<Path>,
+  {
+    let id = SORT_ID.fetch_add(1, AtomicOrdering::Relaxed);
+    let mut runs = self.write_runs(input.as_ref(), id, comparator)?;
+    let run_count = runs.paths.len();
+    let mut pass = 1;
+    while runs.paths.len() > self.fan_in() {
+      runs = self.merge_pass(&runs, id, pass, comparator)?;
+      pass += 1;
+    }
+    // マージ中は開いている一時ファイルと出力の読み書きバッファでmemory_budgetを分け合う
+    let buf_size = (self.memory_budget / (runs.paths.len() + 1)).max(R::SIZE);
+    let writer = BufWriter::with_capacity(buf_size, File::create(output)?);
+    merge_runs(&runs.paths, writer, buf_size, comparator)?;
+    Ok(run_count)
+  }
+
+  // run_count個の一時ファイルを出力にマージし終わるまでのマージの回数
+  pub fn merge_passes(&self, run_count: usize) -> usize {
+    let mut runs = run_count;
+    let mut passes = 1;
+    while runs > self.fan_in() {
+      runs = runs.div_ceil(self.fan_in());
+      passes += 1;
+    }
+    passes
+  }
+
+  fn fan_in(&self) -> usize {
+    self.max_open_runs.max(2)
+  }
+
+  fn run_path(&self, id: usize, pass: usize, index: usize) -> PathBuf {
+    self.temp_dir.join(format!(
+      "bitonic-sorter-{}-{}-{}-{}.run",
+      process::id(),
+      id,
+      pass,
+      index
+    ))
+  }
+
+  // runsをfan_in個ずつ中間の一時ファイルにマージする。元の一時ファイルは呼び出し側でdropした時に消える
+  fn merge_pass<R, F>(
+    &self,
+    runs: &TempRuns,
+    id: usize,
+    pass: usize,
+    comparator: &F,
+  ) -> io::Result<TempRuns>
+  where
+    R: Record,
+    F: Fn(&R, &R) -> Ordering,
+  {
+    let buf_size = (self.memory_budget / (self.fan_in() + 1)).max(R::SIZE);
+    let mut merged = TempRuns { paths: Vec::new() };
+    for group in runs.paths.chunks(self.fan_in()) {
+      let path = self.run_path(id, pass, merged.paths.len());
+      merged.paths.push(path.clone());
+      let writer = BufWriter::with_capacity(buf_size, File::create(&path)?);
+      merge_runs(group, writer, buf_size, comparator)?;
+    }
+    Ok(merged)
+  }
+
+  fn write_runs<R, F>(&self, input: &Path, id: usize, comparator: &F) -> io::Result<TempRuns>
+  where
+    R: Record,
+    F: Sync + Fn(&R, &R) -> Ordering,
+  {
+    let mut reader = BufReader::with_capacity(READ_BUF_SIZE, File::open(input)?);
+    let run_len = self.run_len::<R>();
+    let mut records: Vec<R> = Vec::with_capacity(run_len);
+    let mut runs = TempRuns { paths: Vec::new() };
+
+    loop {
+      read_run(&mut reader, &mut records, run_len)?;
+      if records.is_empty() {
+        break;
+      }
+      fourth::sort_by(&mut records, comparator).map_err(io::Error::other)?;
+
+      let path = self.run_path(id, 0, runs.paths.len());
+      runs.paths.push(path.clone());
+      let mut writer = BufWriter::new(File::create(&path)?);
+      write_records(&mut writer, &records)?;
+      writer.flush()?;
+    }
+    Ok(runs)
+  }
+}
+
+// 作った一時ファイルは途中で失敗しても消す
+struct TempRuns {
+  paths: Vec<PathBuf>,
+}
+
+impl Drop for TempRuns {
+  fn drop(&mut self) {
+    for path in &self.paths {
+      let _ = fs::remove_file(path);
+    }
+  }
+}
+
+// bufが埋まるかEOFまで読む。読めたバイト数を返す
+fn read_full<Rd: Read>(reader: &mut Rd, buf: &mut [u8]) -> io::Result<usize> {
+  let mut filled = 0;
+  while filled < buf.len() {
+    match reader.read(&mut buf[filled..]) {
+      Ok(0) => break,
+      Ok(n) => filled += n,
+      Err(ref e) if e.kind() == io::ErrorKind::Interrupted => {}
+      Err(e) => return Err(e),
+    }
+  }
+  Ok(filled)
+}
+
+// run_len個までのレコードをrecordsに読み込む。EOFに着けばrecordsはrun_lenより短くなる
+fn read_run<R: Record, Rd: Read>(
+  reader: &mut Rd,
+  records: &mut Vec<R>,
+  run_len: usize,
+) -> io::Result<()> {
+  let mut buf = vec![0; R::SIZE];
+  records.clear();
+  while records.len() < run_len {
+    match read_full(reader, &mut buf)? {
+      0 => break,
+      n if n == R::SIZE => records.push(R::read_bytes(&buf)),
+      _ => {
+        return Err(io::Error::new(
+          io::ErrorKind::InvalidData,
+          format!(
+            "The input is not a multiple of the record size. (record size: {})",
+            R::SIZE
+          ),
+        ))
+      }
+    }
+  }
+  Ok(())
+}
+
+fn read_record<R: Record, Rd: Read>(reader: &mut Rd, buf: &mut [u8]) -> io::Result<Option<R>> {
+  match read_full(reader, buf)? {
+    0 => Ok(None),
+    n if n == buf.len() => Ok(Some(R::read_bytes(buf))),
+    _ => Err(io::Error::new(
+      io::ErrorKind::UnexpectedEof,
+      "The run file ends in the middle of a record.",
+    )),
+  }
+}
+
+fn write_records<R: Record, W: Write>(writer: &mut W, records: &[R]) -> io::Result<()> {
+  let mut buf = vec![0; R::SIZE];
+  for record in records {
+    record.write_bytes(&mut buf);
+    writer.write_all(&buf)?;
+  }
+  Ok(())
+}
+
+// 各runの先頭のレコードをcomparatorで比較するヒープに入れて、小さい方から書き出す
+fn merge_runs<R, F, W>(
+  paths: &[PathBuf],
+  mut writer: W,
+  buf_size: usize,
+  comparator: &F,
+) -> io::Result<()>
+where
+  R: Record,
+  F: Fn(&R, &R) -> Ordering,
+  W: Write,
+{
+  let mut buf = vec![0; R::SIZE];
+  let mut readers = Vec::with_capacity(paths.len());
+  let mut heap: Vec<(R, usize)> = Vec::with_capacity(paths.len());
+  for path in paths {
+    let mut reader = BufReader::with_capacity(buf_size, File::open(path)?);
+    if let Some(record) = read_record(&mut reader, &mut buf)? {
+      heap.push((record, readers.len()));
+    }
+    readers.push(reader);
+  }
+  for i in (0..heap.len() / 2).rev() {
+    sift_down(&mut heap, i, comparator);
+  }
+
+  while !heap.is_empty() {
+    heap[0].0.write_bytes(&mut buf);
+    writer.write_all(&buf)?;
+    let run = heap[0].1;
+    match read_record(&mut readers[run], &mut buf)? {
+      Some(record) => heap[0] = (record, run),
+      None => {
+        heap.swap_remove(0);
+      }
+    }
+    sift_down(&mut heap, 0, comparator);
+  }
+  writer.flush()
+}
+
+fn sift_down<R, F>(heap: &mut [(R, usize)], mut i: usize, comparator: &F)
+where
+  F: Fn(&R, &R) -> Ordering,
+{
+  loop {
+    let left = 2 * i + 1;
+    if left >= heap.len() {
+      break;
+    }
+    let right = left + 1;
+    let mut child = left;
+    if right < heap.len() && comparator(&heap[right].0, &heap[left].0) == Ordering::Less {
+      child = right;
+    }
+    if comparator(&heap[child].0, &heap[i].0) == Ordering::Less {
+      heap.swap(child, i);
+      i = child;
+    } else {
+      break;
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::{read_full, write_records, ExternalSorter, Record};
+  use crate::utils::new_u32_vec;
+  use std::env;
+  use std::fs::{self, File};
+  use std::io::{self, BufWriter, Write};
+  use std::path::PathBuf;
+  use std::process;
+
+  // テストごとに別の一時ディレクトリを使い、最後に消す
+  struct TestDir(PathBuf);
+
+  impl TestDir {
+    fn new(name: &str) -> Self {
+      let dir = env::temp_dir().join(format!("bitonic-sorter-test-{}-{}", name, process::id()));
+      let _ = fs::remove_dir_all(&dir);
+      fs::create_dir_all(dir.join("tmp")).unwrap();
+      TestDir(dir)
+    }
+  }
+
+  impl Drop for TestDir {
+    fn drop(&mut self) {
+      let _ = fs::remove_dir_all(&self.0);
+    }
+  }
+
+  fn write_file<R: Record>(path: &PathBuf, records: &[R]) {
+    let mut writer = BufWriter::new(File::create(path).unwrap());
+    write_records(&mut writer, records).unwrap();
+    writer.flush().unwrap();
+  }
+
+  fn read_file<R: Record>(path: &PathBuf) -> Vec<R> {
+    let mut buf = Vec::new();
+    let mut file = File::open(path).unwrap();
+    let mut chunk = vec![0; 4096];
+    loop {
+      let n = read_full(&mut file, &mut chunk).unwrap();
+      buf.extend_from_slice(&chunk[..n]);
+      if n < chunk.len() {
+        break;
+      }
+    }
+    buf.chunks(R::SIZE).map(R::read_bytes).collect()
+  }
+
+  #[test]
+  fn run_len_is_power_of_two() {
+    assert_eq!(ExternalSorter::new().memory_budget(64).run_len::<u32>(), 16);
+    assert_eq!(
+      ExternalSorter::new().memory_budget(100).run_len::<u32>(),
+      16
+    );
+    assert_eq!(ExternalSorter::new().memory_budget(100).run_len::<u64>(), 8);
+    assert_eq!(ExternalSorter::new().memory_budget(1).run_len::<u64>(), 1);
+  }
+
+  #[test]
+  fn sort_file_with_many_runs() {
+    let dir = TestDir::new("many-runs");
+    let input = dir.0.join("input.bin");
+    let output = dir.0.join("output.bin");
+    let x = new_u32_vec(10_000);
+    write_file(&input, &x);
+
+    let sorter = ExternalSorter::new()
+      .memory_budget(256)
+      .temp_dir(dir.0.join("tmp"));
+    let runs = sorter
+      .sort_file(&input, &output, &|a: &u32, b: &u32| a.cmp(b))
+      .unwrap();
+    // 256バイトは64レコードなので157個のrunに分かれる
+    assert_eq!(runs, 157);
+
+    let mut expected = x.clone();
+    expected.sort();
+    assert_eq!(read_file::<u32>(&output), expected);
+    // 一時ファイルは残らない
+    assert_eq!(fs::read_dir(dir.0.join("tmp")).unwrap().count(), 0);
+  }
+
+  #[test]
+  fn sort_file_with_several_merge_passes() {
+    let dir = TestDir::new("merge-passes");
+    let input = dir.0.join("input.bin");
+    let output = dir.0.join("output.bin");
+    let x = new_u32_vec(10_000);
+    write_file(&input, &x);
+
+    // 157個のrunを4個ずつ40個、10個、3個にまとめてから出力にマージする
+    let sorter = ExternalSorter::new()
+      .memory_budget(256)
+      .max_open_runs(4)
+      .temp_dir(dir.0.join("tmp"));
+    assert_eq!(sorter.merge_passes(157), 4);
+    let runs = sorter
+      .sort_file(&input, &output, &|a: &u32, b: &u32| a.cmp(b))
+      .unwrap();
+    assert_eq!(runs, 157);
+
+    let mut expected = x.clone();
+    expected.sort();
+    assert_eq!(read_file::<u32>(&output), expected);
+    assert_eq!(fs::read_dir(dir.0.join("tmp")).unwrap().count(), 0);
+
+    assert_eq!(sorter.merge_passes(0), 1);
+    assert_eq!(sorter.merge_passes(4), 1);
+    assert_eq!(sorter.merge_passes(5), 2);
+    assert_eq!(ExternalSorter::new().max_open_runs(0).merge_passes(3), 2);
+  }
+
+  #[test]
+  fn sort_file_descending_i64() {
+    let dir = TestDir::new("descending");
+    let input = dir.0.join("input.bin");
+    let output = dir.0.join("output.bin");
+    let x: Vec<i64> = new_u32_vec(3000)
+      .into_iter()
+      .map(|v| i64::from(v) - i64::from(u32::MAX / 2))
+      .collect();
+    write_file(&input, &x);
+
+    let sorter = ExternalSorter::new()
+      .memory_budget(1000)
+      .temp_dir(dir.0.join("tmp"));
+    let runs = sorter
+      .sort_file(&input, &output, &|a: &i64, b: &i64| b.cmp(a))
+      .unwrap();
+    assert_eq!(runs, 47);
+
+    let mut expected = x.clone();
+    expected.sort_by(|a, b| b.cmp(a));
+    assert_eq!(read_file::<i64>(&output), expected);
+  }
+
+  #[test]
+  fn sort_empty_file() {
+    let dir = TestDir::new("empty");
+    let input = dir.0.join("input.bin");
+    let output = dir.0.join("output.bin");
+    write_file::<u32>(&input, &[]);
+
+    let sorter = ExternalSorter::new().temp_dir(dir.0.join("tmp"));
+    let runs = sorter
+      .sort_file(&input, &output, &|a: &u32, b: &u32| a.cmp(b))
+      .unwrap();
+    assert_eq!(runs, 0);
+    assert_eq!(read_file::<u32>(&output), vec![]);
+  }
+
+  #[test]
+  fn sort_file_not_multiple_of_record_size() {
+    let dir = TestDir::new("invalid");
+    let input = dir.0.join("input.bin");
+    let output = dir.0.join("output.bin");
+    File::create(&input)
+      .unwrap()
+      .write_all(&[1, 2, 3, 4, 5, 6])
+      .unwrap();
+
+    let sorter = ExternalSorter::new().temp_dir(dir.0.join("tmp"));
+    let err = sorter
+      .sort_file(&input, &output, &|a: &u32, b: &u32| a.cmp(b))
+      .unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    assert_eq!(fs::read_dir(dir.0.join("tmp")).unwrap().count(), 0);
+  }
+}
@@ -0,0 +1,76 @@
+use super::SortOrder;
+use std::cmp::Ordering;
+
+// 浮動小数点数をIEEE 754のtotalOrderで比較するための型。
+// totalOrderでは-0.0 < +0.0で、NaNも符号とペイロードで順番が決まる
+pub trait TotalOrder: Copy {
+  fn total_cmp(&self, other: &Self) -> Ordering;
+  fn is_nan(&self) -> bool;
+}
+
+macro_rules! impl_total_order {
+  ($($t:ty),*) => {
+    $(impl TotalOrder for $t {
+      fn total_cmp(&self, other: &Self) -> Ordering {
+        <$t>::total_cmp(self, other)
+      }
+      fn is_nan(&self) -> bool {
+        <$t>::is_nan(*self)
+      }
+    })*
+  };
+}
+
+impl_total_order!(f32, f64);
+
+// NaNを並べる位置。SortOrderに関係なく、先頭か末尾にまとめる
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NanPosition {
+  First,
+  Last,
+}
+
+// NaN以外はtotalOrderでorderの向きに並べる。降順なら+0.0が-0.0より前に来る。
+// NaN同士はorderに関係なくtotalOrderの昇順にして、同じ入力からは常に同じ並びになるようにする
+pub fn comparator<T: TotalOrder>(
+  order: &SortOrder,
+  nans: NanPosition,
+) -> impl Fn(&T, &T) -> Ordering + Sync {
+  let ascending = match *order {
+    SortOrder::Ascending => true,
+    SortOrder::Descending => false,
+  };
+  let nan_first = nans == NanPosition::First;
+  move |a: &T, b: &T| match (a.is_nan(), b.is_nan()) {
+    (true, true) => a.total_cmp(b),
+    (true, false) if nan_first => Ordering::Less,
+    (true, false) => Ordering::Greater,
+    (false, true) if nan_first => Ordering::Greater,
+    (false, true) => Ordering::Less,
+    (false, false) if ascending => a.total_cmp(b),
+    (false, false) => b.total_cmp(a),
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::{comparator, NanPosition};
+  use crate::SortOrder::*;
+  use std::cmp::Ordering::*;
+
+  #[test]
+  fn comparator_orders_nan_and_zero() {
+    let cmp = comparator::<f64>(&Ascending, NanPosition::Last);
+    assert_eq!(cmp(&-0.0, &0.0), Less);
+    assert_eq!(cmp(&0.0, &0.0), Equal);
+    assert_eq!(cmp(&f64::NAN, &f64::INFINITY), Greater);
+    assert_eq!(cmp(&f64::NEG_INFINITY, &f64::NAN), Less);
+    assert_eq!(cmp(&-f64::NAN, &f64::NAN), Less);
+
+    let cmp = comparator::<f32>(&Descending, NanPosition::First);
+    assert_eq!(cmp(&-0.0, &0.0), Greater);
+    assert_eq!(cmp(&f32::NAN, &f32::INFINITY), Less);
+    assert_eq!(cmp(&1.0, &f32::NAN), Greater);
+    assert_eq!(cmp(&-f32::NAN, &f32::NAN), Less);
+  }
+}
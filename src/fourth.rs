@@ -1,11 +1,23 @@
-use super::SortOrder;
+use super::access::RandomAccessMut;
+use super::float::{self, NanPosition, TotalOrder};
+use super::instrument::Probe;
+use super::network::{self, LEAF_LEN};
+use super::utils::apply_permutation;
+use super::{third, SortError, SortOrder};
 use rayon;
+use rayon::prelude::*;
+use rayon::ThreadPool;
 use std::cmp::Ordering;
 use std::marker::Send;
+use std::sync::Arc;
 
 const PARALLEL_THRETHOLD: usize = 4096;
 
-pub fn sort<T: Ord + Send>(x: &mut [T], order: &SortOrder) -> Result<(), String> {
+pub fn sort<C>(x: &mut C, order: &SortOrder) -> Result<(), SortError>
+where
+  C: RandomAccessMut + ?Sized,
+  C::Item: Ord + Send,
+{
   match *order {
     SortOrder::Ascending => sort_by(x, &|a, b| a.cmp(b)),
     SortOrder::Descending => sort_by(x, &|a, b| b.cmp(a)),
@@ -18,40 +30,192 @@ fn is_power_of_two(x: usize) -> bool {
 
 // Sendは他のスレッドから使われない、安全にスレッド間で受け渡しできることを明示する
 // Syncは他のスレッドで同時に呼ばれても相互に影響がないことを明示する
-pub fn sort_by<T, F>(x: &mut [T], coparator: &F) -> Result<(), String>
+// スライスとして取り出せないコンテナは分割して別スレッドに渡せないので、thirdと同じく逐次でソートする
+pub fn sort_by<C, F>(x: &mut C, coparator: &F) -> Result<(), SortError>
+where
+  C: RandomAccessMut + ?Sized,
+  C::Item: Send,
+  F: Sync + Fn(&C::Item, &C::Item) -> Ordering,
+{
+  match x.as_contiguous_mut() {
+    Some(x) => ParSorter::new().sort_by(x, coparator),
+    None => third::sort_by(x, coparator),
+  }
+}
+
+pub fn sort_by_key<T, K, F>(x: &mut [T], key: &F) -> Result<(), SortError>
 where
   T: Send,
+  K: Ord,
+  F: Sync + Fn(&T) -> K,
+{
+  sort_by(x, &|a, b| key(a).cmp(&key(b)))
+}
+
+// f32/f64をIEEE 754のtotalOrderでソートする。NaNはorderに関係なくnansの位置にまとまり、
+// -0.0は昇順なら+0.0の前、降順なら後に来る
+pub fn sort_floats<T: TotalOrder + Send>(
+  x: &mut [T],
+  order: &SortOrder,
+  nans: NanPosition,
+) -> Result<(), SortError> {
+  sort_by(x, &float::comparator(order, nans))
+}
+
+// sort_by_keyは比較のたびにキーを計算するので、重いキーは先に一度だけ並列に計算しておく
+// (キー, 元の位置)の組をソートするので同じキーの要素は元の順番のままになる
+pub fn sort_by_cached_key<T, K, F>(x: &mut [T], key: &F) -> Result<(), SortError>
+where
+  T: Send + Sync,
+  K: Ord + Send,
+  F: Sync + Fn(&T) -> K,
+{
+  let mut keys: Vec<(K, usize)> = x
+    .par_iter()
+    .map(key)
+    .enumerate()
+    .map(|(i, k)| (k, i))
+    .collect();
+  sort_by(&mut keys, &|a, b| a.cmp(b))?;
+  let order: Vec<usize> = keys.into_iter().map(|(_, i)| i).collect();
+  apply_permutation(x, &order);
+  Ok(())
+}
+
+// bitonic sortは安定ではないので、argsort_byで求めた並びをxに適用する
+pub fn stable_sort_by<T, F>(x: &mut [T], comparator: &F) -> Result<(), SortError>
+where
+  T: Send + Sync,
   F: Sync + Fn(&T, &T) -> Ordering,
 {
-  if is_power_of_two(x.len()) {
-    do_sort(x, true, coparator);
-    Ok(())
-  } else {
-    Err(format!(
-      "The length of x is not a power of two. (x.len(): {})",
-      x.len()
-    ))
+  let order = argsort_by(x, comparator)?;
+  apply_permutation(x, &order);
+  Ok(())
+}
+
+pub fn argsort<T: Ord + Sync>(x: &[T], order: &SortOrder) -> Result<Vec<usize>, SortError> {
+  match *order {
+    SortOrder::Ascending => argsort_by(x, &|a, b| a.cmp(b)),
+    SortOrder::Descending => argsort_by(x, &|a, b| b.cmp(a)),
+  }
+}
+
+// xを変更せずに、ソートした時にi番目に来る要素の位置を返す。
+// 要素の代わりに位置をソートし、比較結果が等しい時は位置で比較するので同じ値の要素は元の順番になる
+pub fn argsort_by<T, F>(x: &[T], comparator: &F) -> Result<Vec<usize>, SortError>
+where
+  T: Sync,
+  F: Sync + Fn(&T, &T) -> Ordering,
+{
+  let mut indices: Vec<usize> = (0..x.len()).collect();
+  sort_by(&mut indices, &|&a: &usize, &b: &usize| {
+    comparator(&x[a], &x[b]).then(a.cmp(&b))
+  })?;
+  Ok(indices)
+}
+
+// 整列済みのaとbをマージした新しいVecを返す
+pub fn merge_sorted<T, F>(a: &[T], b: &[T], comparator: &F) -> Vec<T>
+where
+  T: Clone + Send,
+  F: Sync + Fn(&T, &T) -> Ordering,
+{
+  let mut x = Vec::with_capacity(a.len() + b.len());
+  x.extend_from_slice(a);
+  x.extend_from_slice(b);
+  merge_halves(&mut x, a.len(), comparator);
+  x
+}
+
+// x[..mid_point]とx[mid_point..]がそれぞれ整列済みの時に、その場でマージする。
+// merge_sortedのように長さの違う2つの列を繋げたものも渡せるので、半分ではない境界をmid_pointで受け取り、
+// 並べる向きはcomparatorで決める。mid_pointはx.len()以下でなければならない。
+// 前半を反転すると降順から昇順のbitonic列になるのでsub_sortだけでマージできる。
+// 2のべき乗以外の長さのsub_sortは降順、昇順の順に並んだ列しか扱えないので、後半ではなく前半を反転する
+pub fn merge_halves<T, F>(x: &mut [T], mid_point: usize, comparator: &F)
+where
+  T: Send,
+  F: Sync + Fn(&T, &T) -> Ordering,
+{
+  assert!(
+    mid_point <= x.len(),
+    "mid_point must not exceed the length of x. (mid_point: {}, x.len(): {})",
+    mid_point,
+    x.len()
+  );
+  x[..mid_point].reverse();
+  ParSorter::new().sub_sort(x, true, comparator, &(), 0);
+}
+
+pub fn top_k<T: Ord + Send>(x: &mut [T], k: usize, order: &SortOrder) -> Result<(), SortError> {
+  match *order {
+    SortOrder::Ascending => top_k_by(x, k, &|a, b| a.cmp(b)),
+    SortOrder::Descending => top_k_by(x, k, &|a, b| b.cmp(a)),
   }
 }
 
-fn do_sort<T, F>(x: &mut [T], forward: bool, comparator: &F)
+// comparatorで先に来る方からk個をx[..k]に整列して並べる。x[k..]の順番は決まらない
+// k個ずつのブロックをソートし、隣のブロックと合わせて良い方の半分だけを残すのを繰り返す
+pub fn top_k_by<T, F>(x: &mut [T], k: usize, comparator: &F) -> Result<(), SortError>
 where
   T: Send,
   F: Sync + Fn(&T, &T) -> Ordering,
 {
-  if x.len() > 1 {
-    let mid_point = x.len() / 2;
-    let (first, second) = x.split_at_mut(mid_point);
-    if mid_point >= PARALLEL_THRETHOLD {
-      rayon::join(
-        || do_sort(first, true, comparator),
-        || do_sort(second, false, comparator),
-      );
-    } else {
-      do_sort(first, true, comparator);
-      do_sort(second, false, comparator);
+  if k == 0 {
+    return Ok(());
+  }
+  select(x, k, comparator)
+}
+
+fn select<T, F>(x: &mut [T], k: usize, comparator: &F) -> Result<(), SortError>
+where
+  T: Send,
+  F: Sync + Fn(&T, &T) -> Ordering,
+{
+  if x.len() <= k {
+    return sort_by(x, comparator);
+  }
+  // kの倍数の位置で分けるので、前半は必ずk個以上ある
+  let blocks = x.len().div_ceil(k);
+  let mid_point = blocks / 2 * k;
+  let (first, second) = x.split_at_mut(mid_point);
+  if mid_point >= PARALLEL_THRETHOLD {
+    let (r1, r2) = rayon::join(
+      || select(first, k, comparator),
+      || select(second, k, comparator),
+    );
+    r1?;
+    r2?;
+  } else {
+    select(first, k, comparator)?;
+    select(second, k, comparator)?;
+  }
+
+  // 前半の大きい方から、後半の小さい方からの組で比べて、後半の方が先に来るものを前半に移す
+  let best = &mut first[..k];
+  let mut swapped = false;
+  for (a, b) in best.iter_mut().rev().zip(second.iter_mut()) {
+    if comparator(b, a) != Ordering::Less {
+      break;
     }
-    sub_sort(x, forward, comparator);
+    std::mem::swap(a, b);
+    swapped = true;
+  }
+  // bestは昇順、降順の順に並んだbitonic列になる。
+  // sub_sortはこの形の列を昇順にはマージできないので、降順にマージしてから反転する
+  if swapped {
+    sub_sort(best, false, comparator);
+    best.reverse();
+  }
+  Ok(())
+}
+
+// len未満で最大の2のべき乗を返す。2のべき乗の長さではlen / 2と同じになる
+fn split_point(len: usize) -> usize {
+  if is_power_of_two(len) {
+    len / 2
+  } else {
+    len.next_power_of_two() / 2
   }
 }
 
@@ -60,38 +224,259 @@ where
   T: Send,
   F: Sync + Fn(&T, &T) -> Ordering,
 {
-  if x.len() > 1 {
-    compare_and_swap(x, forward, comparator);
-    let mid_point = x.len() / 2;
-    let (first, second) = x.split_at_mut(mid_point);
-    if mid_point >= PARALLEL_THRETHOLD {
-      rayon::join(
-        || sub_sort(first, forward, comparator),
-        || sub_sort(second, forward, comparator),
-      );
+  ParSorter::new().sub_sort(x, forward, comparator, &(), 0);
+}
+
+// 並列化の閾値、使うスレッドプール、並列に分岐する再帰の深さを指定してソートする
+// スレッドプールを指定しなければrayonのグローバルプールを使う
+pub struct ParSorter {
+  threshold: usize,
+  pool: Option<Arc<ThreadPool>>,
+  max_depth: usize,
+  chunked_merge: bool,
+}
+
+impl Default for ParSorter {
+  fn default() -> Self {
+    Self {
+      threshold: PARALLEL_THRETHOLD,
+      pool: None,
+      max_depth: usize::MAX,
+      chunked_merge: true,
+    }
+  }
+}
+
+impl ParSorter {
+  pub fn new() -> Self {
+    Self::default()
+  }
+
+  // 分割した片側の長さがthreshold以上の時だけ並列に処理する
+  pub fn threshold(mut self, threshold: usize) -> Self {
+    self.threshold = threshold;
+    self
+  }
+
+  // Arcで渡せば複数のソーターで同じプールを共有できる
+  pub fn thread_pool<P: Into<Arc<ThreadPool>>>(mut self, pool: P) -> Self {
+    self.pool = Some(pool.into());
+    self
+  }
+
+  // max_depthより深い再帰では並列に分岐しない。0なら全て逐次で処理する
+  pub fn max_depth(mut self, max_depth: usize) -> Self {
+    self.max_depth = max_depth;
+    self
+  }
+
+  // falseにするとマージの段のcompare_and_swapをチャンクに分けず、mid_pointまで逐次にループする。
+  // 再帰の分岐は並列のままなので、チャンクに分けた効果だけを測れる
+  pub fn chunked_merge(mut self, chunked_merge: bool) -> Self {
+    self.chunked_merge = chunked_merge;
+    self
+  }
+
+  // ソートに使うスレッドの数
+  pub(crate) fn num_threads(&self) -> usize {
+    match self.pool {
+      Some(ref pool) => pool.current_num_threads(),
+      None => rayon::current_num_threads(),
+    }
+  }
+
+  pub fn sort<T: Ord + Send>(&self, x: &mut [T], order: &SortOrder) -> Result<(), SortError> {
+    match *order {
+      SortOrder::Ascending => self.sort_by(x, &|a, b| a.cmp(b)),
+      SortOrder::Descending => self.sort_by(x, &|a, b| b.cmp(a)),
+    }
+  }
+
+  pub fn sort_by<T, F>(&self, x: &mut [T], comparator: &F) -> Result<(), SortError>
+  where
+    T: Send,
+    F: Sync + Fn(&T, &T) -> Ordering,
+  {
+    self.sort_with(x, comparator, &());
+    Ok(())
+  }
+
+  // probeに入れ替えと再帰の深さを知らせながらソートする。instrumentから呼ぶ
+  pub(crate) fn sort_with<T, F, P>(&self, x: &mut [T], comparator: &F, probe: &P)
+  where
+    T: Send,
+    F: Sync + Fn(&T, &T) -> Ordering,
+    P: Probe,
+  {
+    match self.pool {
+      Some(ref pool) => pool.install(|| self.run(x, comparator, probe)),
+      None => self.run(x, comparator, probe),
+    }
+  }
+
+  fn run<T, F, P>(&self, x: &mut [T], comparator: &F, probe: &P)
+  where
+    T: Send,
+    F: Sync + Fn(&T, &T) -> Ordering,
+    P: Probe,
+  {
+    if is_power_of_two(x.len()) {
+      self.do_sort(x, true, comparator, probe, 0);
+    } else {
+      self.do_sort_any(x, true, comparator, probe, 0);
+    }
+  }
+
+  fn is_parallel(&self, len: usize, depth: usize) -> bool {
+    len >= self.threshold && depth < self.max_depth
+  }
+
+  // 2のべき乗以外の長さ用。前半をforwardと逆向き、後半をforward向きにソートすると
+  // 全体がbitonicになり、sub_sortの分割位置を変えるだけでマージできる
+  fn do_sort_any<T, F, P>(
+    &self,
+    x: &mut [T],
+    forward: bool,
+    comparator: &F,
+    probe: &P,
+    depth: usize,
+  ) where
+    T: Send,
+    F: Sync + Fn(&T, &T) -> Ordering,
+    P: Probe,
+  {
+    probe.visit(depth);
+    if x.len() <= LEAF_LEN {
+      network::sort_leaf(x, forward, comparator, probe);
+    } else {
+      let mid_point = x.len() / 2;
+      let (first, second) = x.split_at_mut(mid_point);
+      if self.is_parallel(mid_point, depth) {
+        rayon::join(
+          || self.do_sort_any(first, !forward, comparator, probe, depth + 1),
+          || self.do_sort_any(second, forward, comparator, probe, depth + 1),
+        );
+      } else {
+        self.do_sort_any(first, !forward, comparator, probe, depth + 1);
+        self.do_sort_any(second, forward, comparator, probe, depth + 1);
+      }
+      self.sub_sort(x, forward, comparator, probe, depth);
+    }
+  }
+
+  fn do_sort<T, F, P>(&self, x: &mut [T], forward: bool, comparator: &F, probe: &P, depth: usize)
+  where
+    T: Send,
+    F: Sync + Fn(&T, &T) -> Ordering,
+    P: Probe,
+  {
+    probe.visit(depth);
+    if x.len() <= LEAF_LEN {
+      network::sort_leaf(x, forward, comparator, probe);
     } else {
-      sub_sort(first, forward, comparator);
-      sub_sort(second, forward, comparator);
+      let mid_point = x.len() / 2;
+      let (first, second) = x.split_at_mut(mid_point);
+      if self.is_parallel(mid_point, depth) {
+        rayon::join(
+          || self.do_sort(first, true, comparator, probe, depth + 1),
+          || self.do_sort(second, false, comparator, probe, depth + 1),
+        );
+      } else {
+        self.do_sort(first, true, comparator, probe, depth + 1);
+        self.do_sort(second, false, comparator, probe, depth + 1);
+      }
+      self.sub_sort(x, forward, comparator, probe, depth);
+    }
+  }
+
+  fn sub_sort<T, F, P>(&self, x: &mut [T], forward: bool, comparator: &F, probe: &P, depth: usize)
+  where
+    T: Send,
+    F: Sync + Fn(&T, &T) -> Ordering,
+    P: Probe,
+  {
+    probe.visit(depth);
+    if x.len() > 1 {
+      self.compare_and_swap(x, forward, comparator, probe, depth);
+      let mid_point = split_point(x.len());
+      let (first, second) = x.split_at_mut(mid_point);
+      if self.is_parallel(mid_point, depth) {
+        rayon::join(
+          || self.sub_sort(first, forward, comparator, probe, depth + 1),
+          || self.sub_sort(second, forward, comparator, probe, depth + 1),
+        );
+      } else {
+        self.sub_sort(first, forward, comparator, probe, depth + 1);
+        self.sub_sort(second, forward, comparator, probe, depth + 1);
+      }
     }
   }
+
+  // 比較する組は互いに独立なので、前半と後半をthresholdずつのチャンクに分けて並列に比較する
+  fn compare_and_swap<T, F, P>(
+    &self,
+    x: &mut [T],
+    forward: bool,
+    comparator: &F,
+    probe: &P,
+    depth: usize,
+  ) where
+    T: Send,
+    F: Sync + Fn(&T, &T) -> Ordering,
+    P: Probe,
+  {
+    let mid_point = split_point(x.len());
+    let len = x.len() - mid_point;
+    if !self.chunked_merge || !self.is_parallel(len, depth) {
+      compare_and_swap_with(x, forward, comparator, probe);
+      return;
+    }
+    let swap_condition = if forward {
+      Ordering::Greater
+    } else {
+      Ordering::Less
+    };
+    let chunk_size = self.threshold.max(1);
+    let (first, second) = x.split_at_mut(mid_point);
+    first[..len]
+      .par_chunks_mut(chunk_size)
+      .zip(second.par_chunks_mut(chunk_size))
+      .for_each(|(first, second)| {
+        for (a, b) in first.iter_mut().zip(second.iter_mut()) {
+          if comparator(a, b) == swap_condition {
+            std::mem::swap(a, b);
+            probe.swapped();
+          }
+        }
+      });
+  }
 }
 
 pub fn compare_and_swap<T, F>(x: &mut [T], forward: bool, comparator: &F)
 where
   T: Send,
   F: Sync + Fn(&T, &T) -> Ordering,
+{
+  compare_and_swap_with(x, forward, comparator, &());
+}
+
+fn compare_and_swap_with<T, F, P>(x: &mut [T], forward: bool, comparator: &F, probe: &P)
+where
+  F: Fn(&T, &T) -> Ordering,
+  P: Probe,
 {
   let swap_condition = if forward {
     Ordering::Greater
   } else {
     Ordering::Less
   };
-  let mid_point = x.len() / 2;
-  for i in 0..mid_point {
+  let mid_point = split_point(x.len());
+  for i in 0..(x.len() - mid_point) {
     // Ordは比較をするメソッドを持っているので > で比較ができるようになる。
     // TだけだとコンパイルエラーになるがT: Ordにすると成功する。すごい
     if comparator(&x[i], &x[mid_point + i]) == swap_condition {
       x.swap(i, mid_point + i);
+      probe.swapped();
     }
   }
 }
@@ -99,9 +484,17 @@ where
 #[cfg(test)]
 mod tests {
   // using parent module
-  use super::{is_power_of_two, sort, sort_by};
+  use super::{
+    argsort, argsort_by, compare_and_swap, merge_halves, merge_sorted, sort, sort_by,
+    sort_by_cached_key, sort_by_key, sort_floats, stable_sort_by, top_k, top_k_by, ParSorter,
+  };
+  use rayon::ThreadPoolBuilder;
+  use std::cmp::Reverse;
+  use std::sync::atomic::{AtomicUsize, Ordering};
   // using current crate
-  use crate::utils::{is_sorted_ascending, is_sorted_descending, new_u32_vec};
+  use crate::float::NanPosition;
+  use crate::third;
+  use crate::utils::{apply_permutation, is_sorted_ascending, is_sorted_descending, new_u32_vec};
   use crate::SortOrder::*;
   #[test]
   fn sort_u32_ascending() {
@@ -172,9 +565,40 @@ mod tests {
     );
   }
   #[test]
-  fn sort_to_fail() {
+  fn sort_not_power_of_two() {
     let mut x = vec![10, 30, 11];
-    assert!(sort(&mut x, &Ascending).is_err());
+    assert_eq!(sort(&mut x, &Ascending), Ok(()));
+    assert_eq!(x, vec![10, 11, 30]);
+  }
+
+  #[test]
+  fn sort_any_length_same_as_std() {
+    for len in 0..=2048 {
+      // 偶数長は重複の多いデータを降順で確認する
+      let (mut x, order) = if len % 2 == 0 {
+        let x: Vec<u32> = new_u32_vec(len).into_iter().map(|v| v % 8).collect();
+        (x, Descending)
+      } else {
+        (new_u32_vec(len), Ascending)
+      };
+      let mut expected = x.clone();
+      expected.sort();
+      if let Descending = order {
+        expected.reverse();
+      }
+      assert_eq!(sort(&mut x, &order), Ok(()), "len: {}", len);
+      assert_eq!(x, expected, "len: {}", len);
+    }
+  }
+
+  #[test]
+  fn sort_u32_large_not_power_of_two() {
+    // PARALLEL_THRETHOLDを超えて並列で処理される長さ
+    let mut x = new_u32_vec(50_000);
+    let mut expected = x.clone();
+    expected.sort();
+    assert_eq!(sort_by(&mut x, &|a, b| a.cmp(b)), Ok(()));
+    assert_eq!(x, expected);
   }
 
   #[derive(Debug, PartialEq)]
@@ -232,6 +656,249 @@ mod tests {
     assert_eq!(x, expected);
   }
 
+  #[test]
+  fn sort_students_by_key_age_ascending() {
+    let taro = Student::new("Taro", "Yamada", 16);
+    let hanako = Student::new("Hanako", "Yamada", 14);
+    let kyoko = Student::new("Kyoki", "Ito", 15);
+    let ryosuke = Student::new("Tyosuke", "Hayashi", 17);
+
+    let mut x = vec![&taro, &hanako, &kyoko, &ryosuke];
+
+    let expected = vec![&hanako, &kyoko, &taro, &ryosuke];
+
+    assert_eq!(sort_by_key(&mut x, &|s| s.age), Ok(()));
+
+    assert_eq!(x, expected);
+  }
+
+  #[test]
+  fn sort_students_by_cached_key_name_ascending() {
+    let taro = Student::new("Taro", "Yamada", 16);
+    let hanako = Student::new("Hanako", "Yamada", 14);
+    let kyoko = Student::new("Kyoki", "Ito", 15);
+    let ryosuke = Student::new("Tyosuke", "Hayashi", 17);
+
+    let mut x = vec![&taro, &hanako, &kyoko, &ryosuke, &hanako];
+
+    let expected = vec![&ryosuke, &kyoko, &hanako, &hanako, &taro];
+
+    assert_eq!(
+      sort_by_cached_key(&mut x, &|s| format!("{} {}", s.last_name, s.first_name)),
+      Ok(())
+    );
+
+    assert_eq!(x, expected);
+  }
+
+  #[test]
+  fn sort_by_cached_key_calls_key_once() {
+    // PARALLEL_THRETHOLDを超えて並列で処理される長さ
+    let calls = AtomicUsize::new(0);
+    let mut x = new_u32_vec(50_000);
+    let mut expected = x.clone();
+    expected.sort_by_key(|v| v % 100);
+
+    assert_eq!(
+      sort_by_cached_key(&mut x, &|v| {
+        calls.fetch_add(1, Ordering::Relaxed);
+        v % 100
+      }),
+      Ok(())
+    );
+    assert_eq!(x, expected);
+    assert_eq!(calls.load(Ordering::Relaxed), 50_000);
+  }
+
+  #[test]
+  fn stable_sort_students_by_last_name() {
+    let taro = Student::new("Taro", "Yamada", 16);
+    let hanako = Student::new("Hanako", "Yamada", 14);
+    let kyoko = Student::new("Kyoki", "Ito", 15);
+    let ryosuke = Student::new("Tyosuke", "Hayashi", 17);
+    let ken = Student::new("Ken", "Ito", 16);
+
+    let mut x = vec![&taro, &hanako, &kyoko, &ryosuke, &ken];
+
+    let expected = vec![&ryosuke, &kyoko, &ken, &taro, &hanako];
+
+    assert_eq!(
+      stable_sort_by(&mut x, &|a, b| a.last_name.cmp(&b.last_name)),
+      Ok(())
+    );
+
+    assert_eq!(x, expected);
+  }
+
+  #[test]
+  fn stable_sort_many_duplicates() {
+    for &len in &[0, 1, 7, 64, 1000, 5000] {
+      // (キー, 元の位置)の組をキーだけで比較する
+      let mut x: Vec<(u32, usize)> = new_u32_vec(len)
+        .into_iter()
+        .map(|v| v % 4)
+        .enumerate()
+        .map(|(i, v)| (v, i))
+        .collect();
+      let mut expected = x.clone();
+      expected.sort_by_key(|v| Reverse(v.0));
+
+      assert_eq!(stable_sort_by(&mut x, &|a, b| b.0.cmp(&a.0)), Ok(()));
+      assert_eq!(x, expected, "len: {}", len);
+    }
+  }
+
+  #[test]
+  fn par_sorter_threshold_and_depth() {
+    for &(threshold, max_depth) in &[(1, usize::MAX), (16, 3), (16, 0), (1 << 20, usize::MAX)] {
+      let sorter = ParSorter::new().threshold(threshold).max_depth(max_depth);
+      for &len in &[0, 1, 100, 1024, 3000] {
+        let mut x = new_u32_vec(len);
+        let mut expected = x.clone();
+        expected.sort();
+        assert_eq!(sorter.sort(&mut x, &Ascending), Ok(()));
+        assert_eq!(x, expected, "threshold: {}, len: {}", threshold, len);
+      }
+    }
+  }
+
+  #[test]
+  fn par_sorter_compare_and_swap_same_as_serial() {
+    let sorter = ParSorter::new().threshold(100);
+    for &len in &[2, 1000, 1500, 4097] {
+      for &forward in &[true, false] {
+        let mut x = new_u32_vec(len);
+        let mut expected = x.clone();
+        compare_and_swap(&mut expected, forward, &|a, b| a.cmp(b));
+        sorter.compare_and_swap(&mut x, forward, &|a, b| a.cmp(b), &(), 0);
+        assert_eq!(x, expected, "len: {}", len);
+      }
+    }
+  }
+
+  #[test]
+  fn par_sorter_without_chunked_merge() {
+    let sorter = ParSorter::new().threshold(64).chunked_merge(false);
+    for &len in &[0, 1, 1000, 4096, 10_000] {
+      let mut x = new_u32_vec(len);
+      let mut expected = x.clone();
+      expected.sort();
+      assert_eq!(sorter.sort(&mut x, &Ascending), Ok(()));
+      assert_eq!(x, expected, "len: {}", len);
+    }
+  }
+
+  #[test]
+  fn par_sorter_uses_thread_pool() {
+    let pool = ThreadPoolBuilder::new().num_threads(3).build().unwrap();
+    let sorter = ParSorter::new().threshold(64).thread_pool(pool);
+    let threads = AtomicUsize::new(0);
+    let mut x = new_u32_vec(10_000);
+    assert_eq!(
+      sorter.sort_by(&mut x, &|a, b| {
+        threads.store(rayon::current_num_threads(), Ordering::Relaxed);
+        b.cmp(a)
+      }),
+      Ok(())
+    );
+    assert!(is_sorted_descending(&x));
+    assert_eq!(threads.load(Ordering::Relaxed), 3);
+  }
+
+  #[test]
+  fn argsort_students_by_age() {
+    let taro = Student::new("Taro", "Yamada", 16);
+    let hanako = Student::new("Hanako", "Yamada", 14);
+    let kyoko = Student::new("Kyoki", "Ito", 15);
+    let ryosuke = Student::new("Tyosuke", "Hayashi", 17);
+
+    let x = vec![&taro, &hanako, &kyoko, &ryosuke];
+
+    assert_eq!(
+      argsort_by(&x, &|a, b| a.age.cmp(&b.age)),
+      Ok(vec![1, 2, 0, 3])
+    );
+    // 元のスライスは変更されない
+    assert_eq!(x, vec![&taro, &hanako, &kyoko, &ryosuke]);
+  }
+
+  #[test]
+  fn argsort_u32_large() {
+    let x = new_u32_vec(10_000);
+    let indices = argsort(&x, &Descending).unwrap();
+    let sorted: Vec<u32> = indices.iter().map(|&i| x[i]).collect();
+    let mut expected = x.clone();
+    expected.sort_by_key(|&v| Reverse(v));
+    assert_eq!(sorted, expected);
+
+    let mut y = x.clone();
+    apply_permutation(&mut y, &indices);
+    assert_eq!(y, expected);
+  }
+
+  #[test]
+  fn merge_sorted_any_length() {
+    let lens = [
+      (0, 0),
+      (0, 5),
+      (5, 0),
+      (1, 1),
+      (3, 8),
+      (8, 8),
+      (100, 37),
+      (1000, 30_000),
+    ];
+    for &(a_len, b_len) in &lens {
+      let mut a = new_u32_vec(a_len);
+      let mut b: Vec<u32> = new_u32_vec(a_len + b_len).split_off(a_len);
+      a.sort();
+      b.sort();
+      let mut expected = a.clone();
+      expected.extend_from_slice(&b);
+      expected.sort();
+      let merged = merge_sorted(&a, &b, &|x, y| x.cmp(y));
+      assert_eq!(merged, expected, "a.len(): {}, b.len(): {}", a_len, b_len);
+    }
+  }
+
+  #[test]
+  #[should_panic(expected = "mid_point must not exceed the length of x")]
+  fn merge_halves_mid_point_out_of_range() {
+    let mut x = vec![1, 2, 3];
+    merge_halves(&mut x, 4, &|a: &u32, b: &u32| a.cmp(b));
+  }
+
+  #[test]
+  fn merge_halves_descending() {
+    let mut x = vec![9, 7, 7, 2, 0, 10, 8, 3, 1];
+    merge_halves(&mut x, 5, &|a: &u32, b: &u32| b.cmp(a));
+    assert_eq!(x, vec![10, 9, 8, 7, 7, 3, 2, 1, 0]);
+  }
+
+  #[test]
+  fn top_k_same_as_sort_and_truncate() {
+    for &len in &[0, 1, 2, 10, 100, 1000, 5000, 50_000] {
+      for &k in &[0, 1, 3, 8, 100, 1000, 5000, 60_000] {
+        let mut x: Vec<u32> = new_u32_vec(len).into_iter().map(|v| v % 5000).collect();
+        let mut expected = x.clone();
+        expected.sort();
+        expected.truncate(k);
+        assert_eq!(top_k_by(&mut x, k, &|a, b| a.cmp(b)), Ok(()));
+        assert_eq!(&x[..k.min(len)], &expected[..], "len: {}, k: {}", len, k);
+      }
+    }
+  }
+
+  #[test]
+  fn top_k_slowest_requests() {
+    let mut x = new_u32_vec(20_000);
+    let mut expected = x.clone();
+    expected.sort_by_key(|&v| Reverse(v));
+    expected.truncate(100);
+    assert_eq!(top_k(&mut x, 100, &Descending), Ok(()));
+    assert_eq!(&x[..100], &expected[..]);
+  }
+
   #[test]
   fn sort_u32_large() {
     {
@@ -245,4 +912,45 @@ mod tests {
       assert_eq!(is_sorted_descending(&x), true);
     }
   }
+
+  #[test]
+  fn sort_floats_same_as_third() {
+    // ペイロードや符号の違うNaNと、-0.0を含むf32の列
+    let x: Vec<f32> = new_u32_vec(20_000)
+      .into_iter()
+      .map(|v| match v % 97 {
+        0 => f32::from_bits(v | 0x7f80_0001),
+        1 => -0.0,
+        2 => 0.0,
+        _ => (v as i32) as f32 / 1024.0,
+      })
+      .collect();
+    let bits = |x: &[f32]| x.iter().map(|v| v.to_bits()).collect::<Vec<_>>();
+
+    for &nans in &[NanPosition::First, NanPosition::Last] {
+      for order in &[Ascending, Descending] {
+        let mut expected = x.clone();
+        assert_eq!(third::sort_floats(&mut expected, order, nans), Ok(()));
+        let mut y = x.clone();
+        assert_eq!(sort_floats(&mut y, order, nans), Ok(()));
+        assert_eq!(bits(&y), bits(&expected));
+
+        let nan_count = x.iter().filter(|v| v.is_nan()).count();
+        let nan_range = match nans {
+          NanPosition::First => 0..nan_count,
+          NanPosition::Last => y.len() - nan_count..y.len(),
+        };
+        assert!(y[nan_range].iter().all(|v| v.is_nan()));
+      }
+    }
+
+    // 昇順・NaNが末尾の時は、f32::total_cmpで並べてから符号が負のNaNを末尾に移したものになる
+    let mut sorted = x.clone();
+    sorted.sort_by(|a, b| a.total_cmp(b));
+    let (nan, mut expected): (Vec<f32>, Vec<f32>) = sorted.into_iter().partition(|v| v.is_nan());
+    expected.extend(nan);
+    let mut y = x;
+    assert_eq!(sort_floats(&mut y, &Ascending, NanPosition::Last), Ok(()));
+    assert_eq!(bits(&y), bits(&expected));
+  }
 }
@@ -0,0 +1,267 @@
+use super::SortOrder;
+use std::cmp::Ordering;
+use std::future::{Future, IntoFuture};
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+// イベントループの中でもソートできるように、ネットワークを少しずつ進めるソート。
+// iterative::sort_byと同じネットワークを、1回のステップでslice_len箇所ずつ比較していく。
+// 比較と入れ替えは1組ずつ完結するので、途中でdropして止めてもxは元の列の並べ替えのままになる
+
+pub fn sort<'a, T: Ord>(
+  x: &'a mut [T],
+  order: &SortOrder,
+  slice_len: usize,
+) -> IncrementalSort<'a, T, fn(&T, &T) -> Ordering> {
+  let comparator: fn(&T, &T) -> Ordering = match *order {
+    SortOrder::Ascending => |a, b| a.cmp(b),
+    SortOrder::Descending => |a, b| b.cmp(a),
+  };
+  sort_by(x, comparator, slice_len)
+}
+
+// 状態として持ち続けるので、comparatorは参照ではなく値で受け取る。&Fを渡してもよい
+pub fn sort_by<T, F>(x: &mut [T], comparator: F, slice_len: usize) -> IncrementalSort<'_, T, F>
+where
+  F: Fn(&T, &T) -> Ordering,
+{
+  let total = passes(x.len()) * x.len();
+  IncrementalSort {
+    x,
+    comparator,
+    slice_len: slice_len.max(1),
+    k: 2,
+    mask: 1,
+    i: 0,
+    done: 0,
+    total,
+  }
+}
+
+// ステップごとの進み具合。doneとtotalは比較する位置を調べた数で、実際の比較はそれ以下になる
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Progress {
+  pub done: usize,
+  pub total: usize,
+}
+
+impl Progress {
+  pub fn is_finished(&self) -> bool {
+    self.done == self.total
+  }
+}
+
+// Iteratorとしてnextを呼ぶたびに1ステップ進める。awaitすると1ステップごとにエグゼキューターに戻る
+pub struct IncrementalSort<'a, T, F> {
+  x: &'a mut [T],
+  comparator: F,
+  slice_len: usize,
+  // iterative::sort_byのステージkと、比較する相手を決めるmask
+  k: usize,
+  mask: usize,
+  // 今のパスで次に調べる位置
+  i: usize,
+  done: usize,
+  total: usize,
+}
+
+impl<'a, T, F> IncrementalSort<'a, T, F>
+where
+  F: Fn(&T, &T) -> Ordering,
+{
+  pub fn progress(&self) -> Progress {
+    Progress {
+      done: self.done,
+      total: self.total,
+    }
+  }
+
+  pub fn is_finished(&self) -> bool {
+    self.k / 2 >= self.x.len()
+  }
+
+  // slice_len箇所まで比較を進める。終わっていれば何もしない
+  pub fn step(&mut self) -> Progress {
+    let len = self.x.len();
+    let mut budget = self.slice_len;
+    while budget > 0 && !self.is_finished() {
+      let end = len.min(self.i + budget);
+      for i in self.i..end {
+        let l = i ^ self.mask;
+        if l > i && l < len && (self.comparator)(&self.x[i], &self.x[l]) == Ordering::Greater {
+          self.x.swap(i, l);
+        }
+      }
+      budget -= end - self.i;
+      self.done += end - self.i;
+      self.i = end;
+      if self.i == len {
+        self.next_pass();
+      }
+    }
+    self.progress()
+  }
+
+  // ステージの最初のパスはk - 1、その後はk / 4から1まで半分ずつにしたmaskで比較する
+  fn next_pass(&mut self) {
+    self.i = 0;
+    self.mask = if self.mask == self.k - 1 {
+      self.k / 4
+    } else {
+      self.mask / 2
+    };
+    if self.mask == 0 {
+      self.k *= 2;
+      self.mask = self.k - 1;
+    }
+  }
+}
+
+impl<'a, T, F> Iterator for IncrementalSort<'a, T, F>
+where
+  F: Fn(&T, &T) -> Ordering,
+{
+  type Item = Progress;
+
+  fn next(&mut self) -> Option<Progress> {
+    if self.is_finished() {
+      None
+    } else {
+      Some(self.step())
+    }
+  }
+}
+
+impl<'a, T, F> IntoFuture for IncrementalSort<'a, T, F>
+where
+  F: Fn(&T, &T) -> Ordering + Unpin,
+{
+  type Output = ();
+  type IntoFuture = SortFuture<'a, T, F>;
+
+  fn into_future(self) -> SortFuture<'a, T, F> {
+    SortFuture { sort: self }
+  }
+}
+
+// pollのたびに1ステップだけ進め、終わっていなければすぐに起こしてもらうようにしてPendingを返す
+pub struct SortFuture<'a, T, F> {
+  sort: IncrementalSort<'a, T, F>,
+}
+
+impl<'a, T, F> Future for SortFuture<'a, T, F>
+where
+  F: Fn(&T, &T) -> Ordering + Unpin,
+{
+  type Output = ();
+
+  fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+    let sort = &mut self.get_mut().sort;
+    sort.step();
+    if sort.is_finished() {
+      Poll::Ready(())
+    } else {
+      cx.waker().wake_by_ref();
+      Poll::Pending
+    }
+  }
+}
+
+// 長さlenをソートするのに必要なパスの数。2のべき乗に切り上げた長さを2^pとすると
+// ステージkではlog2(k)回のパスがあるので、合計はp(p + 1) / 2になる
+fn passes(len: usize) -> usize {
+  if len < 2 {
+    return 0;
+  }
+  let p = len.next_power_of_two().trailing_zeros() as usize;
+  p * (p + 1) / 2
+}
+
+#[cfg(test)]
+mod tests {
+  use super::{sort, sort_by, Progress};
+  use crate::utils::new_u32_vec;
+  use crate::SortOrder::*;
+  use std::future::{Future, IntoFuture};
+  use std::pin::pin;
+  use std::task::{Context, Poll, Waker};
+
+  #[test]
+  fn steps_sort_any_length() {
+    for &len in &[0, 1, 2, 3, 100, 1000, 1024, 3001] {
+      let mut x = new_u32_vec(len);
+      let mut expected = x.clone();
+      expected.sort();
+
+      let steps: Vec<Progress> = sort(&mut x, &Ascending, 257).collect();
+      assert_eq!(x, expected);
+      if let Some(last) = steps.last() {
+        assert!(last.is_finished());
+      }
+      // 途中のステップはslice_lenずつ進む
+      for pair in steps.windows(2) {
+        assert_eq!(
+          pair[1].done - pair[0].done,
+          257.min(pair[1].total - pair[0].done)
+        );
+      }
+    }
+  }
+
+  #[test]
+  fn step_is_bounded() {
+    let mut x = new_u32_vec(1000);
+    let calls = std::cell::Cell::new(0);
+    let mut sort = sort_by(
+      &mut x,
+      |a: &u32, b: &u32| {
+        calls.set(calls.get() + 1);
+        b.cmp(a)
+      },
+      64,
+    );
+    assert_eq!(sort.progress().total, 55 * 1000);
+    while !sort.is_finished() {
+      calls.set(0);
+      sort.step();
+      assert!(calls.get() <= 64);
+    }
+    assert!(x.windows(2).all(|p| p[0] >= p[1]));
+  }
+
+  #[test]
+  fn cancel_leaves_permutation() {
+    let x = new_u32_vec(5000);
+    let mut y = x.clone();
+    {
+      let mut sort = sort(&mut y, &Ascending, 100);
+      for _ in 0..37 {
+        sort.next();
+      }
+      assert!(!sort.progress().is_finished());
+    }
+    let mut a = x;
+    let mut b = y.clone();
+    a.sort();
+    b.sort();
+    assert_eq!(a, b);
+    assert!(!y.windows(2).all(|p| p[0] <= p[1]));
+  }
+
+  #[test]
+  fn future_yields_between_steps() {
+    let mut x = new_u32_vec(4096);
+    let mut expected = x.clone();
+    expected.sort_by(|a, b| b.cmp(a));
+
+    let mut future = pin!(sort(&mut x, &Descending, 1024).into_future());
+    let mut cx = Context::from_waker(Waker::noop());
+    let mut polls = 1;
+    while future.as_mut().poll(&mut cx) == Poll::Pending {
+      polls += 1;
+    }
+    // 78パス * 4096箇所を1024箇所ずつ進める
+    assert_eq!(polls, 78 * 4);
+    assert_eq!(x, expected);
+  }
+}
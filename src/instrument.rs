@@ -0,0 +1,237 @@
+use super::fourth::ParSorter;
+use super::{third, SortError};
+use std::cmp::Ordering;
+use std::sync::atomic::{self, AtomicU64, AtomicUsize};
+
+// ソートのコストを数えるための関数。比較関数を数える比較関数で包み、入れ替えと再帰の深さは
+// ソートの内部からProbeで知らせてもらう。fourthでは複数のスレッドから数えるのでアトミックを使う
+
+// ソートの途中の出来事を受け取る。普段のソートでは何もしない()を渡すので、計測のコストはかからない
+pub(crate) trait Probe: Sync {
+  fn swapped(&self) {}
+  fn visit(&self, _depth: usize) {}
+}
+
+impl Probe for () {}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SortStats {
+  pub comparisons: u64,
+  pub swaps: u64,
+  // 最も深い再帰呼び出しの深さ。最初の呼び出しが0
+  pub max_depth: usize,
+  // 比較か入れ替えをしたスレッドごとの数。合計はcomparisonsとswapsに等しい
+  pub per_thread: Vec<ThreadWork>,
+}
+
+// threadはrayonのスレッド番号。プールの外のスレッドはNone
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ThreadWork {
+  pub thread: Option<usize>,
+  pub comparisons: u64,
+  pub swaps: u64,
+}
+
+// 長さ2^pのbitonicソートの比較の数。n / 2組の比較がp(p + 1) / 2段あるので、n log2(n)^2に比例する
+pub fn bitonic_comparisons(len: usize) -> u64 {
+  if len < 2 {
+    return 0;
+  }
+  let p = len.next_power_of_two().trailing_zeros() as u64;
+  len.next_power_of_two() as u64 / 2 * p * (p + 1) / 2
+}
+
+pub fn third_sort_by<T, F>(x: &mut [T], comparator: &F) -> Result<SortStats, SortError>
+where
+  F: Fn(&T, &T) -> Ordering,
+{
+  let counters = Counters::new(0);
+  third::sort_slice_with(x, &counters.wrap(comparator), &counters);
+  Ok(counters.into_stats())
+}
+
+pub fn fourth_sort_by<T, F>(x: &mut [T], comparator: &F) -> Result<SortStats, SortError>
+where
+  T: Send,
+  F: Sync + Fn(&T, &T) -> Ordering,
+{
+  par_sort_by(&ParSorter::new(), x, comparator)
+}
+
+// 閾値やスレッドプールを指定したParSorterで数える
+pub fn par_sort_by<T, F>(
+  sorter: &ParSorter,
+  x: &mut [T],
+  comparator: &F,
+) -> Result<SortStats, SortError>
+where
+  T: Send,
+  F: Sync + Fn(&T, &T) -> Ordering,
+{
+  let counters = Counters::new(sorter.num_threads());
+  sorter.sort_with(x, &counters.wrap(comparator), &counters);
+  Ok(counters.into_stats())
+}
+
+// スレッドごとの(比較, 入れ替え)の数。先頭はプールの外のスレッド用で、i番のスレッドはi + 1に数える
+struct Counters {
+  threads: Vec<(AtomicU64, AtomicU64)>,
+  max_depth: AtomicUsize,
+}
+
+impl Counters {
+  fn new(num_threads: usize) -> Self {
+    Self {
+      threads: (0..=num_threads)
+        .map(|_| (AtomicU64::new(0), AtomicU64::new(0)))
+        .collect(),
+      max_depth: AtomicUsize::new(0),
+    }
+  }
+
+  // 今のスレッドの数える場所
+  fn current(&self) -> &(AtomicU64, AtomicU64) {
+    rayon::current_thread_index()
+      .and_then(|i| self.threads.get(i + 1))
+      .unwrap_or(&self.threads[0])
+  }
+
+  // FがSyncなら包んだ比較関数もSyncになり、fourthに渡せる
+  fn wrap<'a, T, F>(&'a self, comparator: &'a F) -> impl Fn(&T, &T) -> Ordering + 'a
+  where
+    F: Fn(&T, &T) -> Ordering,
+  {
+    move |a, b| {
+      self.current().0.fetch_add(1, atomic::Ordering::Relaxed);
+      comparator(a, b)
+    }
+  }
+
+  fn into_stats(self) -> SortStats {
+    let per_thread: Vec<ThreadWork> = self
+      .threads
+      .into_iter()
+      .enumerate()
+      .map(|(i, (comparisons, swaps))| ThreadWork {
+        thread: i.checked_sub(1),
+        comparisons: comparisons.into_inner(),
+        swaps: swaps.into_inner(),
+      })
+      .filter(|work| work.comparisons > 0 || work.swaps > 0)
+      .collect();
+    SortStats {
+      comparisons: per_thread.iter().map(|work| work.comparisons).sum(),
+      swaps: per_thread.iter().map(|work| work.swaps).sum(),
+      max_depth: self.max_depth.into_inner(),
+      per_thread,
+    }
+  }
+}
+
+// 数えた後で合計するだけなので、順序の保証はRelaxedで足りる
+impl Probe for Counters {
+  fn swapped(&self) {
+    self.current().1.fetch_add(1, atomic::Ordering::Relaxed);
+  }
+
+  fn visit(&self, depth: usize) {
+    self.max_depth.fetch_max(depth, atomic::Ordering::Relaxed);
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::{bitonic_comparisons, fourth_sort_by, par_sort_by, third_sort_by};
+  use crate::fourth::ParSorter;
+  use crate::utils::generator::{generate, Shape};
+  use crate::utils::new_u32_vec;
+  use rayon::ThreadPoolBuilder;
+
+  #[test]
+  fn counts_match_network_size() {
+    for p in 1..=14 {
+      let len = 1 << p;
+      let mut x = new_u32_vec(len);
+      let mut expected = x.clone();
+      expected.sort();
+
+      let stats = third_sort_by(&mut x, &|a, b| a.cmp(b)).unwrap();
+      assert_eq!(x, expected);
+      // 2のべき乗の長さでは全ての段で全ての組を比較するので、n / 2 * p(p + 1) / 2回ちょうどになる
+      assert_eq!(stats.comparisons, (len / 2 * p * (p + 1) / 2) as u64);
+      assert_eq!(stats.comparisons, bitonic_comparisons(len));
+      assert!(stats.swaps <= stats.comparisons);
+      // 末端はネットワークでソートするが、sub_sortは長さ1まで分割するので深さはlog2(n)になる
+      if len > 16 {
+        assert_eq!(stats.max_depth, p, "len: {}", len);
+      }
+
+      // fourthは同じネットワークを並列にたどるだけなので、スレッドごとの内訳以外は同じになる
+      let mut y = new_u32_vec(len);
+      let par = fourth_sort_by(&mut y, &|a, b| a.cmp(b)).unwrap();
+      assert_eq!(y, expected);
+      assert_eq!(
+        (par.comparisons, par.swaps, par.max_depth),
+        (stats.comparisons, stats.swaps, stats.max_depth)
+      );
+    }
+  }
+
+  #[test]
+  fn any_length_within_n_log2_n() {
+    for &len in &[0, 1, 3, 17, 100, 1000, 4097, 30_000] {
+      let mut x = new_u32_vec(len);
+      let stats = third_sort_by(&mut x, &|a, b| a.cmp(b)).unwrap();
+      assert!(x.windows(2).all(|p| p[0] <= p[1]));
+      assert!(
+        stats.comparisons <= bitonic_comparisons(len),
+        "len: {}, {:?}",
+        len,
+        stats
+      );
+      assert!(stats.max_depth <= len.next_power_of_two().trailing_zeros() as usize);
+    }
+  }
+
+  #[test]
+  fn swaps_depend_on_input() {
+    let sorted: Vec<u64> = generate(0, 4096, &Shape::Sorted);
+    let mut x = sorted.clone();
+    let stats = third_sort_by(&mut x, &|a, b| a.cmp(b)).unwrap();
+    assert_eq!(stats.comparisons, bitonic_comparisons(4096));
+    // 整列済みでも途中で逆向きに並べるので入れ替えは0にならないが、比較の数は変わらない
+    let mut y: Vec<u64> = generate(0, 4096, &Shape::Random);
+    let random = third_sort_by(&mut y, &|a, b| a.cmp(b)).unwrap();
+    assert_eq!(random.comparisons, stats.comparisons);
+    assert_ne!(random.swaps, stats.swaps);
+  }
+
+  #[test]
+  fn per_thread_work_adds_up() {
+    let pool = ThreadPoolBuilder::new().num_threads(2).build().unwrap();
+    let sorter = ParSorter::new().threshold(64).thread_pool(pool);
+    let mut x = new_u32_vec(10_000);
+    let stats = par_sort_by(&sorter, &mut x, &|a, b| b.cmp(a)).unwrap();
+    assert!(x.windows(2).all(|p| p[0] >= p[1]));
+
+    let mut y = new_u32_vec(10_000);
+    let serial = third_sort_by(&mut y, &|a, b| b.cmp(a)).unwrap();
+    assert_eq!(stats.comparisons, serial.comparisons);
+    assert_eq!(stats.swaps, serial.swaps);
+    assert_eq!(stats.max_depth, serial.max_depth);
+
+    assert!(stats.per_thread.iter().all(|work| work.thread < Some(2)));
+    assert_eq!(
+      stats
+        .per_thread
+        .iter()
+        .map(|work| work.comparisons)
+        .sum::<u64>(),
+      stats.comparisons
+    );
+    assert_eq!(
+      stats.per_thread.iter().map(|work| work.swaps).sum::<u64>(),
+      stats.swaps
+    );
+  }
+}
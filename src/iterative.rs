@@ -0,0 +1,91 @@
+use super::{SortError, SortOrder};
+use std::cmp::Ordering;
+
+pub fn sort<T: Ord>(x: &mut [T], order: &SortOrder) -> Result<(), SortError> {
+  match *order {
+    SortOrder::Ascending => sort_by(x, &|a, b| a.cmp(b)),
+    SortOrder::Descending => sort_by(x, &|a, b| b.cmp(a)),
+  }
+}
+
+// 再帰の代わりにステージkとステップjの二重ループでネットワークをたどる
+// 比較は全て同じ向きにして、各ステージの最初のステップでブロックの後半を反転して比較する。
+// x.len()以降に最大値が詰まっているとみなせるので、範囲外との比較を飛ばせば任意の長さでソートできる
+pub fn sort_by<T, F>(x: &mut [T], comparator: &F) -> Result<(), SortError>
+where
+  F: Fn(&T, &T) -> Ordering,
+{
+  let len = x.len();
+  let mut k = 2;
+  while k / 2 < len {
+    compare_and_swap(x, k - 1, comparator);
+    let mut j = k / 4;
+    while j > 0 {
+      compare_and_swap(x, j, comparator);
+      j /= 2;
+    }
+    k *= 2;
+  }
+  Ok(())
+}
+
+// i番目とi ^ mask番目を比較する。maskがk - 1の時はブロック内で反転した位置と比較する
+fn compare_and_swap<T, F>(x: &mut [T], mask: usize, comparator: &F)
+where
+  F: Fn(&T, &T) -> Ordering,
+{
+  let len = x.len();
+  for i in 0..len {
+    let l = i ^ mask;
+    if l > i && l < len && comparator(&x[i], &x[l]) == Ordering::Greater {
+      x.swap(i, l);
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::{sort, sort_by};
+  use crate::third;
+  use crate::utils::{is_sorted_ascending, is_sorted_descending, new_u32_vec};
+  use crate::SortOrder::*;
+
+  #[test]
+  fn sort_u32_ascending() {
+    let mut x: Vec<u32> = vec![10, 30, 11, 20, 4, 330, 21, 110];
+    assert_eq!(sort(&mut x, &Ascending), Ok(()));
+    assert_eq!(x, vec![4, 10, 11, 20, 21, 30, 110, 330]);
+  }
+
+  #[test]
+  fn sort_u32_descending() {
+    let mut x: Vec<u32> = vec![10, 30, 11, 20, 4, 330, 21, 110];
+    assert_eq!(sort(&mut x, &Descending), Ok(()));
+    assert_eq!(x, vec![330, 110, 30, 21, 20, 11, 10, 4]);
+  }
+
+  #[test]
+  fn sort_same_as_third() {
+    for len in 0..=1100 {
+      let mut x: Vec<u32> = new_u32_vec(len).into_iter().map(|v| v % 64).collect();
+      let mut expected = x.clone();
+      assert_eq!(third::sort_by(&mut expected, &|a, b| b.cmp(a)), Ok(()));
+      assert_eq!(sort_by(&mut x, &|a, b| b.cmp(a)), Ok(()));
+      assert_eq!(x, expected, "len: {}", len);
+    }
+  }
+
+  #[test]
+  fn sort_u32_large() {
+    {
+      let mut x = new_u32_vec(65536);
+      assert_eq!(sort(&mut x, &Ascending), Ok(()));
+      assert!(is_sorted_ascending(&x));
+    }
+    {
+      let mut x = new_u32_vec(65537);
+      assert_eq!(sort(&mut x, &Descending), Ok(()));
+      assert!(is_sorted_descending(&x));
+    }
+  }
+}
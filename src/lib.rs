@@ -1,20 +1,101 @@
+pub mod access;
+pub mod columns;
+pub mod compare;
+pub mod external;
 pub mod first;
+pub mod float;
 pub mod fourth;
+pub mod incremental;
+pub mod instrument;
+pub mod iterative;
+pub mod network;
+pub mod sample;
 pub mod second;
+pub mod simd;
+pub mod sorter;
 pub mod third;
 pub mod utils;
 
 pub mod primitive;
 
+use std::cmp::Ordering;
+use std::error::Error;
+use std::fmt;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum SortOrder {
     Ascending,
     Descending,
 }
 
+impl SortOrder {
+    // 昇順で比較した結果をこの向きの結果に変える。Descendingなら逆にする
+    pub fn apply(&self, ordering: Ordering) -> Ordering {
+        match *self {
+            SortOrder::Ascending => ordering,
+            SortOrder::Descending => ordering.reverse(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SortError {
+    // 2のべき乗の長さしか扱えないソートに渡された。next_validは次に有効な長さ
+    NotPowerOfTwo { len: usize, next_valid: usize },
+    // 一緒に並べ替える列の長さがキーの列と違う
+    LengthMismatch { expected: usize, actual: usize },
+}
+
+impl SortError {
+    pub fn not_power_of_two(len: usize) -> Self {
+        SortError::NotPowerOfTwo {
+            len,
+            next_valid: len.next_power_of_two(),
+        }
+    }
+}
+
+impl fmt::Display for SortError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SortError::NotPowerOfTwo { len, next_valid } => write!(
+                f,
+                "The length of x is not a power of two. (x.len(): {}, next valid length: {})",
+                len, next_valid
+            ),
+            SortError::LengthMismatch { expected, actual } => write!(
+                f,
+                "The length of the column does not match the keys. (expected: {}, actual: {})",
+                expected, actual
+            ),
+        }
+    }
+}
+
+impl Error for SortError {}
+
 #[cfg(test)]
 mod tests {
+    use super::SortError;
+
     #[test]
     fn it_works() {
         assert_eq!(2 + 2, 4);
     }
+
+    #[test]
+    fn sort_error_display() {
+        let err = SortError::not_power_of_two(5);
+        assert_eq!(
+            err,
+            SortError::NotPowerOfTwo {
+                len: 5,
+                next_valid: 8
+            }
+        );
+        assert_eq!(
+            err.to_string(),
+            "The length of x is not a power of two. (x.len(): 5, next valid length: 8)"
+        );
+    }
 }
@@ -0,0 +1,198 @@
+use super::instrument::Probe;
+use std::cmp::Ordering;
+use std::convert::TryInto;
+
+// 長さが決まっている小さな配列用のソーティングネットワーク。
+// 比較する位置の組をconst fnでコンパイル時に求めておき、再帰せずに順番に比較する。
+// 位置も回数も定数なので、コンパイラがループを展開して比較の列だけになる
+
+// sort_fixedで扱える最大の長さ
+pub const MAX_FIXED_LEN: usize = 32;
+
+// 再帰のソートがネットワークに切り替える長さ
+pub(crate) const LEAF_LEN: usize = 16;
+
+// 長さ32の時の比較の数。log2(32) * (log2(32) + 1) / 2段で、各段で16組を比較する
+const MAX_PAIRS: usize = 240;
+
+pub fn sort_fixed<T: Ord, const N: usize>(x: &mut [T; N]) {
+  sort_fixed_by(x, &|a: &T, b: &T| a.cmp(b));
+}
+
+// N > MAX_FIXED_LENではコンパイルエラーになる
+pub fn sort_fixed_by<T, F, const N: usize>(x: &mut [T; N], comparator: &F)
+where
+  F: Fn(&T, &T) -> Ordering,
+{
+  apply(x, comparator, &());
+}
+
+fn apply<T, F, P, const N: usize>(x: &mut [T; N], comparator: &F, probe: &P)
+where
+  F: Fn(&T, &T) -> Ordering,
+  P: Probe,
+{
+  let network = &Network::<N>::PAIRS;
+  for &(i, j) in &network.pairs[..network.len] {
+    let (i, j) = (i as usize, j as usize);
+    if comparator(&x[i], &x[j]) == Ordering::Greater {
+      x.swap(i, j);
+      probe.swapped();
+    }
+  }
+}
+
+// 比較する位置の組 (i, j)。i < jで、x[i] > x[j]なら入れ替える
+struct Pairs {
+  pairs: [(u8, u8); MAX_PAIRS],
+  len: usize,
+}
+
+struct Network<const N: usize>;
+
+impl<const N: usize> Network<N> {
+  const PAIRS: Pairs = pairs(N);
+}
+
+// iterative::sort_byと同じネットワーク。比較は全て昇順向きで、各ステージの最初のステップで
+// ブロックの後半を反転した位置と比較する。n以降には最大値があるとみなして比較を飛ばすので
+// 2のべき乗以外の長さでもソートできる
+const fn pairs(n: usize) -> Pairs {
+  assert!(
+    n <= MAX_FIXED_LEN,
+    "sorting network supports up to 32 elements"
+  );
+  let mut result = Pairs {
+    pairs: [(0, 0); MAX_PAIRS],
+    len: 0,
+  };
+  let mut k = 2;
+  while k / 2 < n {
+    let mut mask = k - 1;
+    while mask > 0 {
+      let mut i = 0;
+      while i < n {
+        let l = i ^ mask;
+        if l > i && l < n {
+          result.pairs[result.len] = (i as u8, l as u8);
+          result.len += 1;
+        }
+        i += 1;
+      }
+      mask = if mask == k - 1 { k / 4 } else { mask / 2 };
+    }
+    k *= 2;
+  }
+  result
+}
+
+// 長さLEAF_LEN以下のxをforwardの向きにソートする。再帰のソートの末端から呼ぶ
+pub(crate) fn sort_leaf<T, F, P>(x: &mut [T], forward: bool, comparator: &F, probe: &P)
+where
+  F: Fn(&T, &T) -> Ordering,
+  P: Probe,
+{
+  if forward {
+    dispatch(x, comparator, probe);
+  } else {
+    dispatch(x, &|a: &T, b: &T| comparator(b, a), probe);
+  }
+}
+
+macro_rules! dispatch_fixed {
+  ($x:ident, $comparator:ident, $probe:ident, $($n:literal)*) => {
+    match $x.len() {
+      0 | 1 => {}
+      $($n => apply::<T, F, P, $n>($x.try_into().unwrap(), $comparator, $probe),)*
+      len => panic!("no sorting network for length {}", len),
+    }
+  };
+}
+
+fn dispatch<T, F, P>(x: &mut [T], comparator: &F, probe: &P)
+where
+  F: Fn(&T, &T) -> Ordering,
+  P: Probe,
+{
+  dispatch_fixed!(x, comparator, probe, 2 3 4 5 6 7 8 9 10 11 12 13 14 15 16);
+}
+
+#[cfg(test)]
+mod tests {
+  use super::{pairs, sort_fixed, sort_fixed_by, sort_leaf, LEAF_LEN, MAX_FIXED_LEN};
+  use crate::utils::new_u32_vec;
+  use std::convert::TryInto;
+
+  // 0-1原理: 0と1だけの全ての入力をソートできれば任意の入力をソートできる
+  fn sorts_all_zero_one<const N: usize>() {
+    for bits in 0_u64..(1 << N) {
+      let mut x: [u8; N] = [0; N];
+      for (i, v) in x.iter_mut().enumerate() {
+        *v = (bits >> i & 1) as u8;
+      }
+      sort_fixed(&mut x);
+      assert!(x.windows(2).all(|p| p[0] <= p[1]), "{:?}", x);
+    }
+  }
+
+  #[test]
+  fn fixed_networks_sort_zero_one() {
+    sorts_all_zero_one::<0>();
+    sorts_all_zero_one::<1>();
+    sorts_all_zero_one::<2>();
+    sorts_all_zero_one::<3>();
+    sorts_all_zero_one::<4>();
+    sorts_all_zero_one::<5>();
+    sorts_all_zero_one::<7>();
+    sorts_all_zero_one::<8>();
+    sorts_all_zero_one::<11>();
+    sorts_all_zero_one::<16>();
+  }
+
+  #[test]
+  fn pairs_count() {
+    assert_eq!(pairs(2).len, 1);
+    assert_eq!(pairs(4).len, 6);
+    assert_eq!(pairs(8).len, 24);
+    assert_eq!(pairs(16).len, 80);
+    assert_eq!(pairs(MAX_FIXED_LEN).len, 240);
+  }
+
+  #[test]
+  fn sort_fixed_32() {
+    let x = new_u32_vec(MAX_FIXED_LEN);
+    let mut y: [u32; MAX_FIXED_LEN] = x.as_slice().try_into().unwrap();
+    let mut expected = x;
+    expected.sort();
+    sort_fixed(&mut y);
+    assert_eq!(&y[..], &expected[..]);
+
+    sort_fixed_by(&mut y, &|a, b| b.cmp(a));
+    expected.reverse();
+    assert_eq!(&y[..], &expected[..]);
+  }
+
+  #[test]
+  fn sort_fixed_strings() {
+    let mut x = ["Rust", "is", "fast", "and", "memory-efficient"];
+    sort_fixed_by(&mut x, &|a: &&str, b: &&str| {
+      a.len().cmp(&b.len()).then(a.cmp(b))
+    });
+    assert_eq!(x, ["is", "and", "Rust", "fast", "memory-efficient"]);
+  }
+
+  #[test]
+  fn sort_leaf_any_length() {
+    for len in 0..=LEAF_LEN {
+      let mut x = new_u32_vec(len);
+      let mut expected = x.clone();
+      expected.sort();
+      sort_leaf(&mut x, true, &|a: &u32, b: &u32| a.cmp(b), &());
+      assert_eq!(x, expected);
+
+      sort_leaf(&mut x, false, &|a: &u32, b: &u32| a.cmp(b), &());
+      expected.reverse();
+      assert_eq!(x, expected);
+    }
+  }
+}
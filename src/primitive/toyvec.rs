@@ -1,3 +1,6 @@
+use crate::access::RandomAccessMut;
+use std::cmp::Ordering;
+
 // lifetimeをVec<T>に似たベクタの実装で学ぶ
 // 1. ベクタは連続したメモリ領域に格納し、この領域(elements)をヒープに確保する
 // 2. pushメソッドで要素を追加できる。elementsのcapを超えた場合は現在の2倍を確保しなおす
@@ -120,6 +123,27 @@ impl<T: Default> ToyVec<T> {
   }
 }
 
+// 使っているのはelementsの先頭len個だけなので、その部分をスライスとしてソートさせる
+impl<T> RandomAccessMut for ToyVec<T> {
+  type Item = T;
+
+  fn len(&self) -> usize {
+    self.len
+  }
+  fn swap(&mut self, a: usize, b: usize) {
+    self.elements[..self.len].swap(a, b)
+  }
+  fn compare<F>(&self, a: usize, b: usize, comparator: &F) -> Ordering
+  where
+    F: Fn(&T, &T) -> Ordering,
+  {
+    comparator(&self.elements[..self.len][a], &self.elements[..self.len][b])
+  }
+  fn as_contiguous_mut(&mut self) -> Option<&mut [T]> {
+    Some(&mut self.elements[..self.len])
+  }
+}
+
 impl<'vec, T: Default> IntoIterator for &'vec ToyVec<T> {
   type Item = &'vec T;
   type IntoIter = Iter<'vec, T>;
@@ -152,6 +176,7 @@ impl<'vec, T> Iterator for Iter<'vec, T> {
 #[cfg(test)]
 mod tests {
   use crate::primitive::toyvec::ToyVec;
+  use crate::{fourth, third, SortOrder};
   #[test]
   fn run_basic() {
     let mut v = ToyVec::new();
@@ -204,4 +229,30 @@ mod tests {
       }
     }
   }
+
+  #[test]
+  fn sort_toyvec() {
+    let names = ["Java Finch", "Budferifar", "Hill Mynah", "Canary", "Zebra"];
+    let mut v = ToyVec::new();
+    for name in &names {
+      v.push(name.to_string());
+    }
+    // capacityは8なので、後ろの使っていない要素はソートに含まれない
+    assert_eq!(v.capacity(), 8);
+    assert_eq!(third::sort(&mut v, &SortOrder::Ascending), Ok(()));
+    let sorted: Vec<&String> = v.iter().collect();
+    assert_eq!(
+      sorted,
+      vec!["Budferifar", "Canary", "Hill Mynah", "Java Finch", "Zebra"]
+    );
+
+    let by_len = |a: &String, b: &String| b.len().cmp(&a.len()).then(a.cmp(b));
+    assert_eq!(fourth::sort_by(&mut v, &by_len), Ok(()));
+    let sorted: Vec<&String> = v.iter().collect();
+    assert_eq!(
+      sorted,
+      vec!["Budferifar", "Hill Mynah", "Java Finch", "Canary", "Zebra"]
+    );
+    assert_eq!(v.len(), 5);
+  }
 }
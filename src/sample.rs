@@ -0,0 +1,294 @@
+use super::fourth;
+use super::{third, SortError, SortOrder};
+use rand::{Rng, SeedableRng};
+use rand_pcg::Pcg64Mcg;
+use rayon::prelude::*;
+use std::cmp::Ordering;
+use std::mem::MaybeUninit;
+use std::ptr;
+
+// これより短い列はバケットに分けずにそのままソートする
+const SAMPLE_THRESHOLD: usize = 1 << 14;
+
+// バケットの境界1つあたりに取るサンプルの数
+const OVERSAMPLING: usize = 32;
+
+pub fn sort<T: Ord + Send + Sync>(x: &mut [T], order: &SortOrder) -> Result<(), SortError> {
+  SampleSorter::new().sort(x, order)
+}
+
+pub fn sort_by<T, F>(x: &mut [T], comparator: &F) -> Result<(), SortError>
+where
+  T: Send + Sync,
+  F: Sync + Fn(&T, &T) -> Ordering,
+{
+  SampleSorter::new().sort_by(x, comparator)
+}
+
+// サンプルソート。ランダムに取ったサンプルから境界の値を選んでxをバケットに分け、
+// バケットごとに並列にソートする。バケットは値の順に並んでいるので、繋げれば全体がソート済みになる
+pub struct SampleSorter {
+  buckets: Option<usize>,
+  oversampling: usize,
+  threshold: usize,
+  seed: u64,
+}
+
+impl Default for SampleSorter {
+  fn default() -> Self {
+    Self {
+      buckets: None,
+      oversampling: OVERSAMPLING,
+      threshold: SAMPLE_THRESHOLD,
+      seed: 0,
+    }
+  }
+}
+
+impl SampleSorter {
+  pub fn new() -> Self {
+    Self::default()
+  }
+
+  // 指定しなければrayonのスレッド数と同じ数のバケットに分ける
+  pub fn buckets(mut self, buckets: usize) -> Self {
+    self.buckets = Some(buckets);
+    self
+  }
+
+  // 多く取るほどバケットの大きさが揃うが、サンプルのソートに時間がかかる
+  pub fn oversampling(mut self, oversampling: usize) -> Self {
+    self.oversampling = oversampling;
+    self
+  }
+
+  // thresholdより短い列はfourth::sort_byでそのままソートする
+  pub fn threshold(mut self, threshold: usize) -> Self {
+    self.threshold = threshold;
+    self
+  }
+
+  // サンプルを選ぶ乱数のシード。同じシードなら同じ境界でバケットに分ける
+  pub fn seed(mut self, seed: u64) -> Self {
+    self.seed = seed;
+    self
+  }
+
+  pub fn sort<T: Ord + Send + Sync>(
+    &self,
+    x: &mut [T],
+    order: &SortOrder,
+  ) -> Result<(), SortError> {
+    match *order {
+      SortOrder::Ascending => self.sort_by(x, &|a, b| a.cmp(b)),
+      SortOrder::Descending => self.sort_by(x, &|a, b| b.cmp(a)),
+    }
+  }
+
+  pub fn sort_by<T, F>(&self, x: &mut [T], comparator: &F) -> Result<(), SortError>
+  where
+    T: Send + Sync,
+    F: Sync + Fn(&T, &T) -> Ordering,
+  {
+    let buckets = self
+      .buckets
+      .unwrap_or_else(rayon::current_num_threads)
+      .max(1);
+    if x.len() < self.threshold.max(2) || buckets == 1 {
+      return fourth::sort_by(x, comparator);
+    }
+
+    let sizes = self.partition(x, buckets, comparator)?;
+
+    // 重複が多いと1つのバケットに偏ることがあるので、バケットの中もfourthで並列にソートする
+    let mut rest = x;
+    let mut slices = Vec::with_capacity(sizes.len());
+    for size in sizes {
+      let (bucket, tail) = rest.split_at_mut(size);
+      slices.push(bucket);
+      rest = tail;
+    }
+    slices
+      .into_par_iter()
+      .try_for_each(|bucket| fourth::sort_by(bucket, comparator))
+  }
+
+  // xをバケットの順に並べ替え、各バケットの長さを返す
+  fn partition<T, F>(
+    &self,
+    x: &mut [T],
+    buckets: usize,
+    comparator: &F,
+  ) -> Result<Vec<usize>, SortError>
+  where
+    T: Send + Sync,
+    F: Sync + Fn(&T, &T) -> Ordering,
+  {
+    let len = x.len();
+    let mut rng = Pcg64Mcg::seed_from_u64(self.seed);
+    let mut sample: Vec<usize> = (0..buckets * self.oversampling.max(1))
+      .map(|_| rng.gen_range(0, len))
+      .collect();
+    third::sort_by(&mut sample, &|&a, &b| comparator(&x[a], &x[b]))?;
+
+    // i番目のバケットにはsplitters[i - 1] <= e < splitters[i]の要素が入る
+    let splitters: Vec<&T> = (1..buckets)
+      .map(|i| &x[sample[i * sample.len() / buckets]])
+      .collect();
+    let bucket_of = |e: &T| splitters.partition_point(|s| comparator(s, e) != Ordering::Greater);
+
+    // チャンクごとに各バケットの要素数を数える
+    let chunk_len = len.div_ceil(rayon::current_num_threads() * 4);
+    let counts: Vec<Vec<usize>> = x
+      .par_chunks(chunk_len)
+      .map(|chunk| {
+        let mut count = vec![0; buckets];
+        chunk.iter().for_each(|e| count[bucket_of(e)] += 1);
+        count
+      })
+      .collect();
+    let sizes: Vec<usize> = (0..buckets)
+      .map(|b| counts.iter().map(|count| count[b]).sum())
+      .collect();
+
+    // 作業領域をバケット、チャンクの順に切り分けると、各チャンクが書き込む範囲は互いに重ならない
+    let mut scratch: Vec<MaybeUninit<T>> = Vec::with_capacity(len);
+    scratch.resize_with(len, MaybeUninit::uninit);
+    let mut slots: Vec<Vec<&mut [MaybeUninit<T>]>> =
+      counts.iter().map(|_| Vec::with_capacity(buckets)).collect();
+    let mut rest = &mut scratch[..];
+    for b in 0..buckets {
+      for (slot, count) in slots.iter_mut().zip(&counts) {
+        let (head, tail) = std::mem::take(&mut rest).split_at_mut(count[b]);
+        slot.push(head);
+        rest = tail;
+      }
+    }
+
+    // 各チャンクが自分の要素を並列に作業領域へ移す。比較関数がpanicしても、
+    // xは全ての要素を持ったままで、作業領域の複製はdropされない
+    x.par_chunks(chunk_len)
+      .zip(slots.into_par_iter())
+      .for_each(|(chunk, mut slot)| {
+        let mut next = vec![0; buckets];
+        for e in chunk {
+          let id = bucket_of(e);
+          let dest = slot[id]
+            .get_mut(next[id])
+            .expect("the comparator returned inconsistent results");
+          // SAFETY: eをビット単位で複製するだけ。所有権は下でxに書き戻すまで作業領域に移らない
+          dest.write(unsafe { ptr::read(e) });
+          next[id] += 1;
+        }
+      });
+
+    // 数えた数と同じだけ書き込んだので、作業領域は全て初期化されていて、xの要素の並べ替えになっている。
+    // xの元の値は作業領域に移ったので、dropせずに上書きする
+    x.par_chunks_mut(chunk_len)
+      .zip(scratch.par_chunks(chunk_len))
+      .for_each(|(x, scratch)| unsafe {
+        ptr::copy_nonoverlapping(scratch.as_ptr() as *const T, x.as_mut_ptr(), x.len());
+      });
+    Ok(sizes)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::{sort, sort_by, SampleSorter};
+  use crate::utils::generator::{generate, Shape};
+  use crate::utils::new_u32_vec;
+  use crate::SortOrder::*;
+  use std::panic::{self, AssertUnwindSafe};
+  use std::sync::atomic::{AtomicUsize, Ordering};
+
+  #[test]
+  fn sort_same_as_std() {
+    for &len in &[0, 1, 2, 1000, 50_000, 100_003] {
+      let mut x = new_u32_vec(len);
+      let mut expected = x.clone();
+      expected.sort();
+      assert_eq!(sort(&mut x, &Ascending), Ok(()));
+      assert_eq!(x, expected);
+
+      expected.reverse();
+      assert_eq!(sort(&mut x, &Descending), Ok(()));
+      assert_eq!(x, expected);
+    }
+  }
+
+  #[test]
+  fn sort_by_struct_key() {
+    let mut x: Vec<(u32, String)> = new_u32_vec(30_000)
+      .into_iter()
+      .map(|v| (v % 1000, v.to_string()))
+      .collect();
+    let mut expected = x.clone();
+    expected.sort_by(|a, b| b.0.cmp(&a.0).then_with(|| a.1.cmp(&b.1)));
+    assert_eq!(
+      sort_by(&mut x, &|a, b| b.0.cmp(&a.0).then_with(|| a.1.cmp(&b.1))),
+      Ok(())
+    );
+    assert_eq!(x, expected);
+  }
+
+  #[test]
+  fn sort_skewed_shapes() {
+    let shapes = [
+      Shape::AllEqual,
+      Shape::Sorted,
+      Shape::OrganPipe,
+      Shape::Zipf {
+        distinct: 5,
+        exponent: 2.0,
+      },
+    ];
+    for shape in &shapes {
+      let mut x: Vec<u64> = generate(1, 40_000, shape);
+      let mut expected = x.clone();
+      expected.sort();
+      assert_eq!(sort(&mut x, &Ascending), Ok(()), "{:?}", shape);
+      assert_eq!(x, expected, "{:?}", shape);
+    }
+  }
+
+  #[test]
+  fn sorter_options() {
+    let x = new_u32_vec(5000);
+    let mut expected = x.clone();
+    expected.sort();
+    for &buckets in &[1, 2, 7, 64, 5000] {
+      let sorter = SampleSorter::new()
+        .buckets(buckets)
+        .oversampling(4)
+        .threshold(16)
+        .seed(42);
+      let mut y = x.clone();
+      assert_eq!(sorter.sort(&mut y, &Ascending), Ok(()));
+      assert_eq!(y, expected, "buckets: {}", buckets);
+    }
+  }
+
+  #[test]
+  fn panic_while_partitioning_keeps_elements() {
+    let x: Vec<String> = new_u32_vec(5000).iter().map(|v| v.to_string()).collect();
+    let mut y = x.clone();
+    let sorter = SampleSorter::new().buckets(8).threshold(16);
+    // サンプルのソートと要素数を数え終わり、作業領域に移している途中でpanicさせる
+    let calls = AtomicUsize::new(0);
+    let result = panic::catch_unwind(AssertUnwindSafe(|| {
+      sorter.sort_by(&mut y, &|a: &String, b: &String| {
+        if calls.fetch_add(1, Ordering::Relaxed) == 20_000 {
+          panic!("comparator failed");
+        }
+        a.cmp(b)
+      })
+    }));
+    assert!(result.is_err());
+
+    let mut expected = x;
+    expected.sort();
+    y.sort();
+    assert_eq!(y, expected);
+  }
+}
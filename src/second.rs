@@ -1,6 +1,6 @@
-use super::SortOrder;
+use super::{SortError, SortOrder};
 
-pub fn sort<T: Ord>(x: &mut [T], order: &SortOrder) -> Result<(), String> {
+pub fn sort<T: Ord>(x: &mut [T], order: &SortOrder) -> Result<(), SortError> {
   if x.len().is_power_of_two() {
     match *order {
       SortOrder::Ascending => do_sort(x, true),
@@ -8,10 +8,7 @@ pub fn sort<T: Ord>(x: &mut [T], order: &SortOrder) -> Result<(), String> {
     }
     Ok(())
   } else {
-    Err(format!(
-      "The length of x is not a power of two. (x.len(): {})",
-      x.len()
-    ))
+    Err(SortError::not_power_of_two(x.len()))
   }
 }
 
@@ -49,6 +46,7 @@ mod tests {
   // using parent module
   use super::sort;
   // using current crate
+  use crate::SortError;
   use crate::SortOrder::*;
   #[test]
   fn sort_u32_ascending() {
@@ -121,6 +119,12 @@ mod tests {
   #[test]
   fn sort_to_fail() {
     let mut x = vec![10, 30, 11];
-    assert!(sort(&mut x, &Ascending).is_err());
+    assert_eq!(
+      sort(&mut x, &Ascending),
+      Err(SortError::NotPowerOfTwo {
+        len: 3,
+        next_valid: 4
+      })
+    );
   }
 }
@@ -0,0 +1,214 @@
+use super::{SortError, SortOrder};
+
+// min/maxを展開して並べる数。レジスタの幅ではなく、LLVMが自動ベクトル化しやすいように
+// ループを展開する幅で、128bitのSSE2/NEONなら1回の展開でu32が2レジスタ分、u64が4レジスタ分になる
+const LANES: usize = 8;
+
+// min/maxをレーン単位で計算できるプリミティブ型
+// b < aの時だけ入れ替えるので、NaNが混ざっても入力の並べ替えになる
+pub trait SimdPrimitive: Copy + PartialOrd {}
+
+macro_rules! impl_simd_primitive {
+  ($($t:ty),*) => {
+    $(impl SimdPrimitive for $t {})*
+  };
+}
+
+impl_simd_primitive!(u32, i32, u64, f32);
+
+pub fn sort_primitive<T: SimdPrimitive>(x: &mut [T], order: &SortOrder) -> Result<(), SortError> {
+  sort_with(x, order, min_max)
+}
+
+// sort_primitiveと同じネットワークを、展開しないスカラーのmin/maxでたどる。
+// ベクトル化の効果を測るためのベンチマーク用
+pub fn sort_primitive_scalar<T: SimdPrimitive>(
+  x: &mut [T],
+  order: &SortOrder,
+) -> Result<(), SortError> {
+  sort_with(x, order, min_max_scalar)
+}
+
+// 前半と後半の組を比較して入れ替える関数
+type MinMax<T> = fn(&mut [T], &mut [T]);
+
+fn sort_with<T: SimdPrimitive>(
+  x: &mut [T],
+  order: &SortOrder,
+  min_max: MinMax<T>,
+) -> Result<(), SortError> {
+  match *order {
+    SortOrder::Ascending => do_sort(x, true, min_max),
+    SortOrder::Descending => do_sort(x, false, min_max),
+  }
+  Ok(())
+}
+
+// third::do_sort_anyと同じ分け方のネットワーク。compare_and_swapだけベクトル化している
+fn do_sort<T: SimdPrimitive>(x: &mut [T], forward: bool, min_max: MinMax<T>) {
+  if x.len() > 1 {
+    let mid_point = x.len() / 2;
+    do_sort(&mut x[..mid_point], !forward, min_max);
+    do_sort(&mut x[mid_point..], forward, min_max);
+    sub_sort_with(x, forward, min_max);
+  }
+}
+
+fn split_point(len: usize) -> usize {
+  if len.is_power_of_two() {
+    len / 2
+  } else {
+    len.next_power_of_two() / 2
+  }
+}
+
+pub fn sub_sort<T: SimdPrimitive>(x: &mut [T], forward: bool) {
+  sub_sort_with(x, forward, min_max);
+}
+
+fn sub_sort_with<T: SimdPrimitive>(x: &mut [T], forward: bool, min_max: MinMax<T>) {
+  if x.len() > 1 {
+    compare_and_swap_with(x, forward, min_max);
+    let mid_point = split_point(x.len());
+    sub_sort_with(&mut x[..mid_point], forward, min_max);
+    sub_sort_with(&mut x[mid_point..], forward, min_max);
+  }
+}
+
+pub fn compare_and_swap<T: SimdPrimitive>(x: &mut [T], forward: bool) {
+  compare_and_swap_with(x, forward, min_max);
+}
+
+fn compare_and_swap_with<T: SimdPrimitive>(x: &mut [T], forward: bool, min_max: MinMax<T>) {
+  let mid_point = split_point(x.len());
+  let (first, second) = x.split_at_mut(mid_point);
+  let first = &mut first[..second.len()];
+  // 降順の時は後半に小さい方を置く
+  if forward {
+    min_max(first, second);
+  } else {
+    min_max(second, first);
+  }
+}
+
+// 分岐のないmin/maxをLANES個ずつ並べて書くとLLVMがSIMD命令に変換する
+#[cfg(any(
+  target_feature = "sse2",
+  target_feature = "neon",
+  target_feature = "simd128"
+))]
+fn min_max<T: SimdPrimitive>(lo: &mut [T], hi: &mut [T]) {
+  let mut lo_chunks = lo.chunks_exact_mut(LANES);
+  let mut hi_chunks = hi.chunks_exact_mut(LANES);
+  for (l, h) in (&mut lo_chunks).zip(&mut hi_chunks) {
+    for i in 0..LANES {
+      let (a, b) = (l[i], h[i]);
+      let swap = b < a;
+      l[i] = if swap { b } else { a };
+      h[i] = if swap { a } else { b };
+    }
+  }
+  min_max_scalar(lo_chunks.into_remainder(), hi_chunks.into_remainder());
+}
+
+#[cfg(not(any(
+  target_feature = "sse2",
+  target_feature = "neon",
+  target_feature = "simd128"
+)))]
+fn min_max<T: SimdPrimitive>(lo: &mut [T], hi: &mut [T]) {
+  min_max_scalar(lo, hi);
+}
+
+fn min_max_scalar<T: SimdPrimitive>(lo: &mut [T], hi: &mut [T]) {
+  for (a, b) in lo.iter_mut().zip(hi.iter_mut()) {
+    if *b < *a {
+      std::mem::swap(a, b);
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::{min_max, min_max_scalar, sort_primitive, sort_primitive_scalar};
+  use crate::utils::new_u32_vec;
+  use crate::SortOrder::*;
+
+  #[test]
+  fn sort_u32_same_as_std() {
+    for &len in &[0, 1, 2, 7, 8, 9, 31, 64, 100, 1023, 4096, 5000] {
+      let mut x = new_u32_vec(len);
+      let mut expected = x.clone();
+      expected.sort();
+      assert_eq!(sort_primitive(&mut x, &Ascending), Ok(()));
+      assert_eq!(x, expected, "len: {}", len);
+
+      expected.reverse();
+      assert_eq!(sort_primitive(&mut x, &Descending), Ok(()));
+      assert_eq!(x, expected, "len: {}", len);
+    }
+  }
+
+  #[test]
+  fn scalar_same_as_vectorized() {
+    for &len in &[0, 1, 9, 1000, 4096] {
+      let mut x = new_u32_vec(len);
+      let mut y = x.clone();
+      assert_eq!(sort_primitive(&mut x, &Descending), Ok(()));
+      assert_eq!(sort_primitive_scalar(&mut y, &Descending), Ok(()));
+      assert_eq!(x, y, "len: {}", len);
+    }
+  }
+
+  #[test]
+  fn sort_i32_u64_same_as_std() {
+    let mut x: Vec<i32> = new_u32_vec(1000).into_iter().map(|v| v as i32).collect();
+    let mut expected = x.clone();
+    expected.sort();
+    assert_eq!(sort_primitive(&mut x, &Ascending), Ok(()));
+    assert_eq!(x, expected);
+
+    let mut x: Vec<u64> = new_u32_vec(1000)
+      .into_iter()
+      .map(|v| u64::from(v) << 20)
+      .collect();
+    let mut expected = x.clone();
+    expected.sort();
+    assert_eq!(sort_primitive(&mut x, &Ascending), Ok(()));
+    assert_eq!(x, expected);
+  }
+
+  #[test]
+  fn sort_f32() {
+    let mut x = vec![0.5_f32, -1.0, 3.25, 2.0, -7.5, 0.0, 1.5];
+    assert_eq!(sort_primitive(&mut x, &Ascending), Ok(()));
+    assert_eq!(x, vec![-7.5, -1.0, 0.0, 0.5, 1.5, 2.0, 3.25]);
+  }
+
+  #[test]
+  fn sort_f32_with_nan_is_permutation() {
+    // NaNの位置は決まらないが、要素が失われたり複製されたりはしない
+    let mut x: Vec<f32> = (0..100).map(|v| v as f32).rev().collect();
+    x[10] = f32::NAN;
+    x[50] = f32::NAN;
+    assert_eq!(sort_primitive(&mut x, &Ascending), Ok(()));
+    assert_eq!(x.iter().filter(|v| v.is_nan()).count(), 2);
+    let mut rest: Vec<f32> = x.into_iter().filter(|v| !v.is_nan()).collect();
+    rest.sort_by(|a, b| a.partial_cmp(b).unwrap());
+    let mut expected: Vec<f32> = (0..100).map(|v| v as f32).collect();
+    expected.retain(|&v| v != 89.0 && v != 49.0);
+    assert_eq!(rest, expected);
+  }
+
+  #[test]
+  fn min_max_same_as_scalar() {
+    let lo = new_u32_vec(100);
+    let hi: Vec<u32> = new_u32_vec(200).split_off(100);
+    let (mut lo1, mut hi1) = (lo.clone(), hi.clone());
+    let (mut lo2, mut hi2) = (lo, hi);
+    min_max(&mut lo1, &mut hi1);
+    min_max_scalar(&mut lo2, &mut hi2);
+    assert_eq!(lo1, lo2);
+    assert_eq!(hi1, hi2);
+  }
+}
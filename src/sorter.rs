@@ -0,0 +1,347 @@
+use super::fourth::ParSorter;
+use super::sample::SampleSorter;
+use super::simd::{self, SimdPrimitive};
+use super::utils::apply_permutation;
+use super::{first, fourth, iterative, second, third, SortError, SortOrder};
+use std::cmp::Ordering;
+
+// 各モジュールのソートを同じ形で呼べるようにする
+// Sorterは昇順か降順でソートできるソーター。扱える要素の型はソーターごとに違う
+pub trait Sorter<T> {
+  fn sort(&self, x: &mut [T], order: &SortOrder) -> Result<(), SortError>;
+}
+
+// 比較関数でソートできるソーター。比較関数を使えないソーターはこのトレイトを実装しないので、
+// 渡すとコンパイルエラーになる。比較関数だけで並べるので、TはOrdでなくてもよい。
+// 並列のソーターでも使えるように、比較関数はSyncに揃えている
+pub trait ComparatorSorter<T> {
+  fn sort_by<F>(&self, x: &mut [T], comparator: &F) -> Result<(), SortError>
+  where
+    F: Sync + Fn(&T, &T) -> Ordering;
+}
+
+// sort_byで昇順か降順の比較関数を渡す。ComparatorSorterのsortの実装に使う
+fn sort_with_order<T, S>(sorter: &S, x: &mut [T], order: &SortOrder) -> Result<(), SortError>
+where
+  T: Ord,
+  S: ComparatorSorter<T>,
+{
+  match *order {
+    SortOrder::Ascending => sorter.sort_by(x, &|a, b| a.cmp(b)),
+    SortOrder::Descending => sorter.sort_by(x, &|a, b| b.cmp(a)),
+  }
+}
+
+// first: u32だけを扱い、比較関数は使えない
+pub struct Naive;
+// second: Ordを実装した型を扱う。長さは2のべき乗のみ
+pub struct Generic;
+// third: 比較関数を使う逐次版
+pub struct Comparator;
+// fourth: rayonで並列化した版
+pub struct Parallel;
+// iterative: 再帰しない版
+pub struct Iterative;
+// simd: プリミティブ型用にcompare_and_swapをベクトル化した版。比較関数は使えない
+pub struct Vectorized;
+// 比較用の標準ライブラリのslice::sort_by
+pub struct StdSorter;
+// 比較用の標準ライブラリのslice::sort_unstable_by
+pub struct StdUnstableSorter;
+
+impl Sorter<u32> for Naive {
+  fn sort(&self, x: &mut [u32], order: &SortOrder) -> Result<(), SortError> {
+    if !x.len().is_power_of_two() {
+      return Err(SortError::not_power_of_two(x.len()));
+    }
+    match *order {
+      SortOrder::Ascending => first::sort(x, true),
+      SortOrder::Descending => first::sort(x, false),
+    }
+    Ok(())
+  }
+}
+
+impl<T: Ord> Sorter<T> for Generic {
+  fn sort(&self, x: &mut [T], order: &SortOrder) -> Result<(), SortError> {
+    second::sort(x, order)
+  }
+}
+
+impl<T> ComparatorSorter<T> for Generic {
+  // secondはOrdしか扱えないので、比較関数でOrdを実装した参照を並べ替えてからxに適用する
+  fn sort_by<F>(&self, x: &mut [T], comparator: &F) -> Result<(), SortError>
+  where
+    F: Sync + Fn(&T, &T) -> Ordering,
+  {
+    let mut keyed: Vec<Keyed<T, F>> = x
+      .iter()
+      .enumerate()
+      .map(|(index, value)| Keyed {
+        value,
+        index,
+        comparator,
+      })
+      .collect();
+    second::sort(&mut keyed, &SortOrder::Ascending)?;
+    let order: Vec<usize> = keyed.iter().map(|k| k.index).collect();
+    apply_permutation(x, &order);
+    Ok(())
+  }
+}
+
+struct Keyed<'a, T, F> {
+  value: &'a T,
+  index: usize,
+  comparator: &'a F,
+}
+
+impl<'a, T, F: Fn(&T, &T) -> Ordering> Ord for Keyed<'a, T, F> {
+  fn cmp(&self, other: &Self) -> Ordering {
+    (self.comparator)(self.value, other.value).then(self.index.cmp(&other.index))
+  }
+}
+
+impl<'a, T, F: Fn(&T, &T) -> Ordering> PartialOrd for Keyed<'a, T, F> {
+  fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+    Some(self.cmp(other))
+  }
+}
+
+impl<'a, T, F: Fn(&T, &T) -> Ordering> PartialEq for Keyed<'a, T, F> {
+  fn eq(&self, other: &Self) -> bool {
+    self.cmp(other) == Ordering::Equal
+  }
+}
+
+impl<'a, T, F: Fn(&T, &T) -> Ordering> Eq for Keyed<'a, T, F> {}
+
+impl<T: Ord> Sorter<T> for Comparator {
+  fn sort(&self, x: &mut [T], order: &SortOrder) -> Result<(), SortError> {
+    sort_with_order(self, x, order)
+  }
+}
+
+impl<T> ComparatorSorter<T> for Comparator {
+  fn sort_by<F>(&self, x: &mut [T], comparator: &F) -> Result<(), SortError>
+  where
+    F: Sync + Fn(&T, &T) -> Ordering,
+  {
+    third::sort_by(x, comparator)
+  }
+}
+
+impl<T: Send + Ord> Sorter<T> for Parallel {
+  fn sort(&self, x: &mut [T], order: &SortOrder) -> Result<(), SortError> {
+    sort_with_order(self, x, order)
+  }
+}
+
+impl<T: Send> ComparatorSorter<T> for Parallel {
+  fn sort_by<F>(&self, x: &mut [T], comparator: &F) -> Result<(), SortError>
+  where
+    F: Sync + Fn(&T, &T) -> Ordering,
+  {
+    fourth::sort_by(x, comparator)
+  }
+}
+
+impl<T: Send + Ord> Sorter<T> for ParSorter {
+  fn sort(&self, x: &mut [T], order: &SortOrder) -> Result<(), SortError> {
+    sort_with_order(self, x, order)
+  }
+}
+
+impl<T: Send> ComparatorSorter<T> for ParSorter {
+  fn sort_by<F>(&self, x: &mut [T], comparator: &F) -> Result<(), SortError>
+  where
+    F: Sync + Fn(&T, &T) -> Ordering,
+  {
+    ParSorter::sort_by(self, x, comparator)
+  }
+}
+
+impl<T: Send + Sync + Ord> Sorter<T> for SampleSorter {
+  fn sort(&self, x: &mut [T], order: &SortOrder) -> Result<(), SortError> {
+    sort_with_order(self, x, order)
+  }
+}
+
+impl<T: Send + Sync> ComparatorSorter<T> for SampleSorter {
+  fn sort_by<F>(&self, x: &mut [T], comparator: &F) -> Result<(), SortError>
+  where
+    F: Sync + Fn(&T, &T) -> Ordering,
+  {
+    SampleSorter::sort_by(self, x, comparator)
+  }
+}
+
+impl<T: Ord> Sorter<T> for Iterative {
+  fn sort(&self, x: &mut [T], order: &SortOrder) -> Result<(), SortError> {
+    sort_with_order(self, x, order)
+  }
+}
+
+impl<T> ComparatorSorter<T> for Iterative {
+  fn sort_by<F>(&self, x: &mut [T], comparator: &F) -> Result<(), SortError>
+  where
+    F: Sync + Fn(&T, &T) -> Ordering,
+  {
+    iterative::sort_by(x, comparator)
+  }
+}
+
+// SimdPrimitiveはOrdでなくてもよいので、f32やf64もソートできる
+impl<T: SimdPrimitive> Sorter<T> for Vectorized {
+  fn sort(&self, x: &mut [T], order: &SortOrder) -> Result<(), SortError> {
+    simd::sort_primitive(x, order)
+  }
+}
+
+impl<T: Ord> Sorter<T> for StdSorter {
+  fn sort(&self, x: &mut [T], order: &SortOrder) -> Result<(), SortError> {
+    sort_with_order(self, x, order)
+  }
+}
+
+impl<T> ComparatorSorter<T> for StdSorter {
+  fn sort_by<F>(&self, x: &mut [T], comparator: &F) -> Result<(), SortError>
+  where
+    F: Sync + Fn(&T, &T) -> Ordering,
+  {
+    x.sort_by(comparator);
+    Ok(())
+  }
+}
+
+impl<T: Ord> Sorter<T> for StdUnstableSorter {
+  fn sort(&self, x: &mut [T], order: &SortOrder) -> Result<(), SortError> {
+    sort_with_order(self, x, order)
+  }
+}
+
+impl<T> ComparatorSorter<T> for StdUnstableSorter {
+  fn sort_by<F>(&self, x: &mut [T], comparator: &F) -> Result<(), SortError>
+  where
+    F: Sync + Fn(&T, &T) -> Ordering,
+  {
+    x.sort_unstable_by(comparator);
+    Ok(())
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::{
+    Comparator, ComparatorSorter, Generic, Iterative, Naive, Parallel, Sorter, StdSorter,
+    StdUnstableSorter, Vectorized,
+  };
+  use crate::fourth::ParSorter;
+  use crate::sample::SampleSorter;
+  use crate::utils::new_u32_vec;
+  use crate::SortError;
+  use crate::SortOrder::*;
+
+  fn check_sort<S: Sorter<u32>>(sorter: &S, len: usize) {
+    let mut x = new_u32_vec(len);
+    let mut expected = x.clone();
+    expected.sort();
+    assert_eq!(sorter.sort(&mut x, &Ascending), Ok(()));
+    assert_eq!(x, expected);
+
+    expected.reverse();
+    assert_eq!(sorter.sort(&mut x, &Descending), Ok(()));
+    assert_eq!(x, expected);
+  }
+
+  fn check_sort_by<S: ComparatorSorter<(u32, &'static str)>>(sorter: &S) {
+    let mut x = vec![(3, "c"), (1, "z"), (2, "b"), (1, "a")];
+    assert_eq!(
+      sorter.sort_by(&mut x, &|a, b| a.0.cmp(&b.0).then_with(|| b.1.cmp(a.1))),
+      Ok(())
+    );
+    assert_eq!(x, vec![(1, "z"), (1, "a"), (2, "b"), (3, "c")]);
+  }
+
+  // f64はOrdではないが、比較関数を渡せばソートできる
+  fn check_sort_by_partial<S: ComparatorSorter<f64>>(sorter: &S) {
+    let mut x = vec![2.5, -1.0, 10.0, -3.25];
+    assert_eq!(
+      sorter.sort_by(&mut x, &|a: &f64, b: &f64| b.partial_cmp(a).unwrap()),
+      Ok(())
+    );
+    assert_eq!(x, vec![10.0, 2.5, -1.0, -3.25]);
+  }
+
+  #[test]
+  fn all_sorters_sort_power_of_two() {
+    check_sort(&Naive, 1024);
+    check_sort(&Generic, 1024);
+    check_sort(&Comparator, 1024);
+    check_sort(&Parallel, 1024);
+    check_sort(&ParSorter::new().threshold(64), 1024);
+    check_sort(&SampleSorter::new().threshold(64), 1024);
+    check_sort(&Iterative, 1024);
+    check_sort(&Vectorized, 1024);
+    check_sort(&StdSorter, 1024);
+    check_sort(&StdUnstableSorter, 1024);
+  }
+
+  #[test]
+  fn sorters_sort_any_length() {
+    check_sort(&Comparator, 1000);
+    check_sort(&Parallel, 1000);
+    check_sort(&SampleSorter::new().threshold(64), 1000);
+    check_sort(&Iterative, 1000);
+    check_sort(&Vectorized, 1000);
+    check_sort(&StdSorter, 1000);
+  }
+
+  #[test]
+  fn sorters_sort_by() {
+    check_sort_by(&Generic);
+    check_sort_by(&Comparator);
+    check_sort_by(&Parallel);
+    check_sort_by(&ParSorter::new());
+    check_sort_by(&SampleSorter::new().threshold(2));
+    check_sort_by(&Iterative);
+    check_sort_by(&StdSorter);
+    check_sort_by(&StdUnstableSorter);
+
+    check_sort_by_partial(&Generic);
+    check_sort_by_partial(&Comparator);
+    check_sort_by_partial(&Parallel);
+    check_sort_by_partial(&SampleSorter::new().threshold(2));
+    check_sort_by_partial(&Iterative);
+    check_sort_by_partial(&StdSorter);
+  }
+
+  #[test]
+  fn vectorized_sorts_floats() {
+    let mut x: Vec<f32> = new_u32_vec(1000).iter().map(|&v| v as f32 - 1e9).collect();
+    let mut expected = x.clone();
+    expected.sort_by(|a, b| a.partial_cmp(b).unwrap());
+    assert_eq!(Sorter::sort(&Vectorized, &mut x, &Ascending), Ok(()));
+    assert_eq!(x, expected);
+  }
+
+  #[test]
+  fn sorters_fail() {
+    let mut x: Vec<u32> = vec![3, 1, 2];
+    assert_eq!(
+      Naive.sort(&mut x, &Ascending),
+      Err(SortError::NotPowerOfTwo {
+        len: 3,
+        next_valid: 4
+      })
+    );
+    assert_eq!(
+      Generic.sort(&mut x, &Ascending),
+      Err(SortError::NotPowerOfTwo {
+        len: 3,
+        next_valid: 4
+      })
+    );
+    assert_eq!(x, vec![3, 1, 2]);
+  }
+}
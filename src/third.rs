@@ -1,7 +1,16 @@
-use super::SortOrder;
+use super::access::RandomAccessMut;
+use super::float::{self, NanPosition, TotalOrder};
+use super::instrument::Probe;
+use super::network::{self, LEAF_LEN};
+use super::utils::apply_permutation;
+use super::{SortError, SortOrder};
 use std::cmp::Ordering;
 
-pub fn sort<T: Ord>(x: &mut [T], order: &SortOrder) -> Result<(), String> {
+pub fn sort<C>(x: &mut C, order: &SortOrder) -> Result<(), SortError>
+where
+  C: RandomAccessMut + ?Sized,
+  C::Item: Ord,
+{
   match *order {
     SortOrder::Ascending => sort_by(x, &|a, b| a.cmp(b)),
     SortOrder::Descending => sort_by(x, &|a, b| b.cmp(a)),
@@ -12,30 +21,178 @@ fn is_power_of_two(x: usize) -> bool {
   x.is_power_of_two()
 }
 
-pub fn sort_by<T, F>(x: &mut [T], coparator: &F) -> Result<(), String>
+// スライスとして取り出せるコンテナはスライスのままソートし、
+// そうでなければ添字の範囲で同じネットワークをたどる
+pub fn sort_by<C, F>(x: &mut C, coparator: &F) -> Result<(), SortError>
+where
+  C: RandomAccessMut + ?Sized,
+  F: Fn(&C::Item, &C::Item) -> Ordering,
+{
+  match x.as_contiguous_mut() {
+    Some(x) => sort_slice_by(x, coparator),
+    None => {
+      let len = x.len();
+      do_sort_range(x, 0, len, true, coparator);
+    }
+  }
+  Ok(())
+}
+
+fn sort_slice_by<T, F>(x: &mut [T], coparator: &F)
+where
+  F: Fn(&T, &T) -> Ordering,
+{
+  sort_slice_with(x, coparator, &());
+}
+
+// probeに入れ替えと再帰の深さを知らせながらソートする。instrumentから呼ぶ
+pub(crate) fn sort_slice_with<T, F, P>(x: &mut [T], coparator: &F, probe: &P)
 where
   F: Fn(&T, &T) -> Ordering,
+  P: Probe,
 {
   if is_power_of_two(x.len()) {
-    do_sort(x, true, coparator);
-    Ok(())
+    do_sort(x, true, coparator, probe, 0);
   } else {
-    Err(format!(
-      "The length of x is not a power of two. (x.len(): {})",
-      x.len()
-    ))
+    do_sort_any(x, true, coparator, probe, 0);
   }
 }
 
-fn do_sort<T, F>(x: &mut [T], forward: bool, comparator: &F)
+pub fn sort_by_key<T, K, F>(x: &mut [T], key: &F) -> Result<(), SortError>
+where
+  K: Ord,
+  F: Fn(&T) -> K,
+{
+  sort_by(x, &|a, b| key(a).cmp(&key(b)))
+}
+
+// f32/f64をIEEE 754のtotalOrderでソートする。NaNはorderに関係なくnansの位置にまとまり、
+// -0.0は昇順なら+0.0の前、降順なら後に来る
+pub fn sort_floats<T: TotalOrder>(
+  x: &mut [T],
+  order: &SortOrder,
+  nans: NanPosition,
+) -> Result<(), SortError> {
+  sort_by(x, &float::comparator(order, nans))
+}
+
+// sort_by_keyは比較のたびにキーを計算するので、重いキーは先に一度だけ計算しておく
+// (キー, 元の位置)の組をソートするので同じキーの要素は元の順番のままになる
+pub fn sort_by_cached_key<T, K, F>(x: &mut [T], key: &F) -> Result<(), SortError>
+where
+  K: Ord,
+  F: Fn(&T) -> K,
+{
+  let mut keys: Vec<(K, usize)> = x.iter().map(key).enumerate().map(|(i, k)| (k, i)).collect();
+  sort_by(&mut keys, &|a, b| a.cmp(b))?;
+  let order: Vec<usize> = keys.into_iter().map(|(_, i)| i).collect();
+  apply_permutation(x, &order);
+  Ok(())
+}
+
+// bitonic sortは安定ではないので、argsort_byで求めた並びをxに適用する
+pub fn stable_sort_by<T, F>(x: &mut [T], comparator: &F) -> Result<(), SortError>
 where
   F: Fn(&T, &T) -> Ordering,
 {
-  if x.len() > 1 {
+  let order = argsort_by(x, comparator)?;
+  apply_permutation(x, &order);
+  Ok(())
+}
+
+pub fn argsort<T: Ord>(x: &[T], order: &SortOrder) -> Result<Vec<usize>, SortError> {
+  match *order {
+    SortOrder::Ascending => argsort_by(x, &|a, b| a.cmp(b)),
+    SortOrder::Descending => argsort_by(x, &|a, b| b.cmp(a)),
+  }
+}
+
+// xを変更せずに、ソートした時にi番目に来る要素の位置を返す。
+// 要素の代わりに位置をソートし、比較結果が等しい時は位置で比較するので同じ値の要素は元の順番になる
+pub fn argsort_by<T, F>(x: &[T], comparator: &F) -> Result<Vec<usize>, SortError>
+where
+  F: Fn(&T, &T) -> Ordering,
+{
+  let mut indices: Vec<usize> = (0..x.len()).collect();
+  sort_by(&mut indices, &|&a: &usize, &b: &usize| {
+    comparator(&x[a], &x[b]).then(a.cmp(&b))
+  })?;
+  Ok(indices)
+}
+
+// 整列済みのaとbをマージした新しいVecを返す
+pub fn merge_sorted<T, F>(a: &[T], b: &[T], comparator: &F) -> Vec<T>
+where
+  T: Clone,
+  F: Fn(&T, &T) -> Ordering,
+{
+  let mut x = Vec::with_capacity(a.len() + b.len());
+  x.extend_from_slice(a);
+  x.extend_from_slice(b);
+  merge_halves(&mut x, a.len(), comparator);
+  x
+}
+
+// x[..mid_point]とx[mid_point..]がそれぞれ整列済みの時に、その場でマージする。
+// merge_sortedのように長さの違う2つの列を繋げたものも渡せるので、半分ではない境界をmid_pointで受け取り、
+// 並べる向きはcomparatorで決める。mid_pointはx.len()以下でなければならない。
+// 前半を反転すると降順から昇順のbitonic列になるのでsub_sortだけでマージできる。
+// 2のべき乗以外の長さのsub_sortは降順、昇順の順に並んだ列しか扱えないので、後半ではなく前半を反転する
+pub fn merge_halves<T, F>(x: &mut [T], mid_point: usize, comparator: &F)
+where
+  F: Fn(&T, &T) -> Ordering,
+{
+  assert!(
+    mid_point <= x.len(),
+    "mid_point must not exceed the length of x. (mid_point: {}, x.len(): {})",
+    mid_point,
+    x.len()
+  );
+  x[..mid_point].reverse();
+  sub_sort(x, true, comparator);
+}
+
+// 2のべき乗以外の長さ用。前半をforwardと逆向き、後半をforward向きにソートすると
+// 全体がbitonicになり、sub_sortの分割位置を変えるだけでマージできる
+// 短くなったら展開済みのネットワークでソートして再帰を打ち切る
+fn do_sort_any<T, F, P>(x: &mut [T], forward: bool, comparator: &F, probe: &P, depth: usize)
+where
+  F: Fn(&T, &T) -> Ordering,
+  P: Probe,
+{
+  probe.visit(depth);
+  if x.len() <= LEAF_LEN {
+    network::sort_leaf(x, forward, comparator, probe);
+  } else {
     let mid_point = x.len() / 2;
-    do_sort(&mut x[..mid_point], true, comparator);
-    do_sort(&mut x[mid_point..], false, comparator);
-    sub_sort(x, forward, comparator);
+    do_sort_any(&mut x[..mid_point], !forward, comparator, probe, depth + 1);
+    do_sort_any(&mut x[mid_point..], forward, comparator, probe, depth + 1);
+    sub_sort_with(x, forward, comparator, probe, depth);
+  }
+}
+
+// len未満で最大の2のべき乗を返す。2のべき乗の長さではlen / 2と同じになる
+fn split_point(len: usize) -> usize {
+  if is_power_of_two(len) {
+    len / 2
+  } else {
+    len.next_power_of_two() / 2
+  }
+}
+
+fn do_sort<T, F, P>(x: &mut [T], forward: bool, comparator: &F, probe: &P, depth: usize)
+where
+  F: Fn(&T, &T) -> Ordering,
+  P: Probe,
+{
+  probe.visit(depth);
+  if x.len() <= LEAF_LEN {
+    network::sort_leaf(x, forward, comparator, probe);
+  } else {
+    let mid_point = x.len() / 2;
+    do_sort(&mut x[..mid_point], true, comparator, probe, depth + 1);
+    do_sort(&mut x[mid_point..], false, comparator, probe, depth + 1);
+    sub_sort_with(x, forward, comparator, probe, depth);
   }
 }
 
@@ -43,39 +200,101 @@ pub fn sub_sort<T, F>(x: &mut [T], forward: bool, comparator: &F)
 where
   F: Fn(&T, &T) -> Ordering,
 {
+  sub_sort_with(x, forward, comparator, &(), 0);
+}
+
+fn sub_sort_with<T, F, P>(x: &mut [T], forward: bool, comparator: &F, probe: &P, depth: usize)
+where
+  F: Fn(&T, &T) -> Ordering,
+  P: Probe,
+{
+  probe.visit(depth);
   if x.len() > 1 {
-    compare_and_swap(x, forward, comparator);
-    let mid_point = x.len() / 2;
-    sub_sort(&mut x[..mid_point], forward, comparator);
-    sub_sort(&mut x[mid_point..], forward, comparator);
+    compare_and_swap_with(x, forward, comparator, probe);
+    let mid_point = split_point(x.len());
+    sub_sort_with(&mut x[..mid_point], forward, comparator, probe, depth + 1);
+    sub_sort_with(&mut x[mid_point..], forward, comparator, probe, depth + 1);
   }
 }
 
 pub fn compare_and_swap<T, F>(x: &mut [T], forward: bool, comparator: &F)
 where
   F: Fn(&T, &T) -> Ordering,
+{
+  compare_and_swap_with(x, forward, comparator, &());
+}
+
+fn compare_and_swap_with<T, F, P>(x: &mut [T], forward: bool, comparator: &F, probe: &P)
+where
+  F: Fn(&T, &T) -> Ordering,
+  P: Probe,
 {
   let swap_condition = if forward {
     Ordering::Greater
   } else {
     Ordering::Less
   };
-  let mid_point = x.len() / 2;
-  for i in 0..mid_point {
+  let mid_point = split_point(x.len());
+  for i in 0..(x.len() - mid_point) {
     // Ordは比較をするメソッドを持っているので > で比較ができるようになる。
     // TだけだとコンパイルエラーになるがT: Ordにすると成功する。すごい
     if comparator(&x[i], &x[mid_point + i]) == swap_condition {
       x.swap(i, mid_point + i);
+      probe.swapped();
     }
   }
 }
 
+// do_sort_anyをx[start..start + len]の添字でたどる版。RandomAccessMutはスライスのように分割できないので、
+// 範囲の先頭と長さを渡していく
+fn do_sort_range<C, F>(x: &mut C, start: usize, len: usize, forward: bool, comparator: &F)
+where
+  C: RandomAccessMut + ?Sized,
+  F: Fn(&C::Item, &C::Item) -> Ordering,
+{
+  if len > 1 {
+    let mid_point = len / 2;
+    do_sort_range(x, start, mid_point, !forward, comparator);
+    do_sort_range(x, start + mid_point, len - mid_point, forward, comparator);
+    sub_sort_range(x, start, len, forward, comparator);
+  }
+}
+
+fn sub_sort_range<C, F>(x: &mut C, start: usize, len: usize, forward: bool, comparator: &F)
+where
+  C: RandomAccessMut + ?Sized,
+  F: Fn(&C::Item, &C::Item) -> Ordering,
+{
+  if len > 1 {
+    let swap_condition = if forward {
+      Ordering::Greater
+    } else {
+      Ordering::Less
+    };
+    let mid_point = split_point(len);
+    for i in start..start + len - mid_point {
+      if x.compare(i, mid_point + i, comparator) == swap_condition {
+        x.swap(i, mid_point + i);
+      }
+    }
+    sub_sort_range(x, start, mid_point, forward, comparator);
+    sub_sort_range(x, start + mid_point, len - mid_point, forward, comparator);
+  }
+}
+
 #[cfg(test)]
 mod tests {
   // using parent module
-  use super::{is_power_of_two, sort, sort_by};
+  use super::{
+    argsort, argsort_by, merge_halves, merge_sorted, sort, sort_by, sort_by_cached_key,
+    sort_by_key, sort_floats, stable_sort_by,
+  };
+  use std::cell::Cell;
+  use std::cmp::Reverse;
   // using current crate
-  use crate::utils::{is_sorted_ascending, is_sorted_descending, new_u32_vec};
+  use crate::compare::{by_field, ComparatorExt};
+  use crate::float::NanPosition;
+  use crate::utils::{apply_permutation, is_sorted_ascending, is_sorted_descending, new_u32_vec};
   use crate::SortOrder::*;
   #[test]
   fn sort_u32_ascending() {
@@ -146,9 +365,30 @@ mod tests {
     );
   }
   #[test]
-  fn sort_to_fail() {
+  fn sort_not_power_of_two() {
     let mut x = vec![10, 30, 11];
-    assert!(sort(&mut x, &Ascending).is_err());
+    assert_eq!(sort(&mut x, &Ascending), Ok(()));
+    assert_eq!(x, vec![10, 11, 30]);
+  }
+
+  #[test]
+  fn sort_any_length_same_as_std() {
+    for len in 0..=2048 {
+      // 偶数長は重複の多いデータを降順で確認する
+      let (mut x, order) = if len % 2 == 0 {
+        let x: Vec<u32> = new_u32_vec(len).into_iter().map(|v| v % 8).collect();
+        (x, Descending)
+      } else {
+        (new_u32_vec(len), Ascending)
+      };
+      let mut expected = x.clone();
+      expected.sort();
+      if let Descending = order {
+        expected.reverse();
+      }
+      assert_eq!(sort(&mut x, &order), Ok(()), "len: {}", len);
+      assert_eq!(x, expected, "len: {}", len);
+    }
   }
 
   #[derive(Debug, PartialEq)]
@@ -206,6 +446,186 @@ mod tests {
     assert_eq!(x, expected);
   }
 
+  #[test]
+  fn sort_students_by_name_with_combinators() {
+    let taro = Student::new("Taro", "Yamada", 16);
+    let hanako = Student::new("Hanako", "Yamada", 14);
+    let kyoko = Student::new("Kyoki", "Ito", 15);
+    let ryosuke = Student::new("Tyosuke", "Hayashi", 17);
+
+    let mut x = vec![&taro, &hanako, &kyoko, &ryosuke];
+
+    // 姓の降順、名の昇順
+    let expected = vec![&hanako, &taro, &kyoko, &ryosuke];
+
+    let comparator = by_field(&Descending, |s: &&Student| &s.last_name)
+      .then(by_field(&Ascending, |s: &&Student| &s.first_name));
+    assert_eq!(sort_by(&mut x, &comparator), Ok(()));
+
+    assert_eq!(x, expected);
+  }
+
+  #[test]
+  fn sort_students_by_key_age_ascending() {
+    let taro = Student::new("Taro", "Yamada", 16);
+    let hanako = Student::new("Hanako", "Yamada", 14);
+    let kyoko = Student::new("Kyoki", "Ito", 15);
+    let ryosuke = Student::new("Tyosuke", "Hayashi", 17);
+
+    let mut x = vec![&taro, &hanako, &kyoko, &ryosuke];
+
+    let expected = vec![&hanako, &kyoko, &taro, &ryosuke];
+
+    assert_eq!(sort_by_key(&mut x, &|s| s.age), Ok(()));
+
+    assert_eq!(x, expected);
+  }
+
+  #[test]
+  fn sort_students_by_cached_key_name_ascending() {
+    let taro = Student::new("Taro", "Yamada", 16);
+    let hanako = Student::new("Hanako", "Yamada", 14);
+    let kyoko = Student::new("Kyoki", "Ito", 15);
+    let ryosuke = Student::new("Tyosuke", "Hayashi", 17);
+
+    let mut x = vec![&taro, &hanako, &kyoko, &ryosuke, &hanako];
+
+    let expected = vec![&ryosuke, &kyoko, &hanako, &hanako, &taro];
+
+    assert_eq!(
+      sort_by_cached_key(&mut x, &|s| format!("{} {}", s.last_name, s.first_name)),
+      Ok(())
+    );
+
+    assert_eq!(x, expected);
+  }
+
+  #[test]
+  fn sort_by_cached_key_calls_key_once() {
+    let calls = Cell::new(0);
+    let mut x = new_u32_vec(1000);
+    let mut expected = x.clone();
+    expected.sort_by_key(|v| v % 100);
+
+    assert_eq!(
+      sort_by_cached_key(&mut x, &|v| {
+        calls.set(calls.get() + 1);
+        v % 100
+      }),
+      Ok(())
+    );
+    assert_eq!(x, expected);
+    assert_eq!(calls.get(), 1000);
+  }
+
+  #[test]
+  fn stable_sort_students_by_last_name() {
+    let taro = Student::new("Taro", "Yamada", 16);
+    let hanako = Student::new("Hanako", "Yamada", 14);
+    let kyoko = Student::new("Kyoki", "Ito", 15);
+    let ryosuke = Student::new("Tyosuke", "Hayashi", 17);
+    let ken = Student::new("Ken", "Ito", 16);
+
+    let mut x = vec![&taro, &hanako, &kyoko, &ryosuke, &ken];
+
+    let expected = vec![&ryosuke, &kyoko, &ken, &taro, &hanako];
+
+    assert_eq!(
+      stable_sort_by(&mut x, &|a, b| a.last_name.cmp(&b.last_name)),
+      Ok(())
+    );
+
+    assert_eq!(x, expected);
+  }
+
+  #[test]
+  fn stable_sort_many_duplicates() {
+    for &len in &[0, 1, 7, 64, 1000, 5000] {
+      // (キー, 元の位置)の組をキーだけで比較する
+      let mut x: Vec<(u32, usize)> = new_u32_vec(len)
+        .into_iter()
+        .map(|v| v % 4)
+        .enumerate()
+        .map(|(i, v)| (v, i))
+        .collect();
+      let mut expected = x.clone();
+      expected.sort_by_key(|v| Reverse(v.0));
+
+      assert_eq!(stable_sort_by(&mut x, &|a, b| b.0.cmp(&a.0)), Ok(()));
+      assert_eq!(x, expected, "len: {}", len);
+    }
+  }
+
+  #[test]
+  fn argsort_students_by_age() {
+    let taro = Student::new("Taro", "Yamada", 16);
+    let hanako = Student::new("Hanako", "Yamada", 14);
+    let kyoko = Student::new("Kyoki", "Ito", 15);
+    let ryosuke = Student::new("Tyosuke", "Hayashi", 17);
+
+    let x = vec![&taro, &hanako, &kyoko, &ryosuke];
+
+    assert_eq!(
+      argsort_by(&x, &|a, b| a.age.cmp(&b.age)),
+      Ok(vec![1, 2, 0, 3])
+    );
+    // 元のスライスは変更されない
+    assert_eq!(x, vec![&taro, &hanako, &kyoko, &ryosuke]);
+  }
+
+  #[test]
+  fn argsort_u32_large() {
+    let x = new_u32_vec(10_000);
+    let indices = argsort(&x, &Descending).unwrap();
+    let sorted: Vec<u32> = indices.iter().map(|&i| x[i]).collect();
+    let mut expected = x.clone();
+    expected.sort_by_key(|&v| Reverse(v));
+    assert_eq!(sorted, expected);
+
+    let mut y = x.clone();
+    apply_permutation(&mut y, &indices);
+    assert_eq!(y, expected);
+  }
+
+  #[test]
+  fn merge_sorted_any_length() {
+    let lens = [
+      (0, 0),
+      (0, 5),
+      (5, 0),
+      (1, 1),
+      (3, 8),
+      (8, 8),
+      (100, 37),
+      (1000, 30_000),
+    ];
+    for &(a_len, b_len) in &lens {
+      let mut a = new_u32_vec(a_len);
+      let mut b: Vec<u32> = new_u32_vec(a_len + b_len).split_off(a_len);
+      a.sort();
+      b.sort();
+      let mut expected = a.clone();
+      expected.extend_from_slice(&b);
+      expected.sort();
+      let merged = merge_sorted(&a, &b, &|x, y| x.cmp(y));
+      assert_eq!(merged, expected, "a.len(): {}, b.len(): {}", a_len, b_len);
+    }
+  }
+
+  #[test]
+  #[should_panic(expected = "mid_point must not exceed the length of x")]
+  fn merge_halves_mid_point_out_of_range() {
+    let mut x = vec![1, 2, 3];
+    merge_halves(&mut x, 4, &|a: &u32, b: &u32| a.cmp(b));
+  }
+
+  #[test]
+  fn merge_halves_descending() {
+    let mut x = vec![9, 7, 7, 2, 0, 10, 8, 3, 1];
+    merge_halves(&mut x, 5, &|a: &u32, b: &u32| b.cmp(a));
+    assert_eq!(x, vec![10, 9, 8, 7, 7, 3, 2, 1, 0]);
+  }
+
   #[test]
   fn sort_u32_large() {
     {
@@ -219,4 +639,39 @@ mod tests {
       assert_eq!(is_sorted_descending(&x), true);
     }
   }
+
+  #[test]
+  fn sort_floats_nan_and_zero() {
+    let x = vec![
+      1.5_f64,
+      f64::NAN,
+      -0.0,
+      0.0,
+      f64::NEG_INFINITY,
+      -2.0,
+      0.0,
+      -0.0,
+    ];
+    // NaNは同じ値同士の比較でも等しくならないので、ビット列で比べる
+    let bits = |x: &[f64]| x.iter().map(|v| v.to_bits()).collect::<Vec<_>>();
+
+    let mut y = x.clone();
+    assert_eq!(sort_floats(&mut y, &Ascending, NanPosition::Last), Ok(()));
+    assert_eq!(
+      bits(&y),
+      bits(&[f64::NEG_INFINITY, -2.0, -0.0, -0.0, 0.0, 0.0, 1.5, f64::NAN])
+    );
+
+    let mut y = x.clone();
+    assert_eq!(sort_floats(&mut y, &Descending, NanPosition::First), Ok(()));
+    assert_eq!(
+      bits(&y),
+      bits(&[f64::NAN, 1.5, 0.0, 0.0, -0.0, -0.0, -2.0, f64::NEG_INFINITY])
+    );
+
+    let mut y = x;
+    assert_eq!(sort_floats(&mut y, &Descending, NanPosition::Last), Ok(()));
+    assert!(y[7].is_nan());
+    assert_eq!(y[0], 1.5);
+  }
 }
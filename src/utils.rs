@@ -1,6 +1,10 @@
+pub mod generator;
+
+use super::SortOrder;
 use rand::distributions::Standard;
 use rand::{Rng, SeedableRng};
 use rand_pcg::Pcg64Mcg;
+use std::cmp::Ordering;
 
 pub fn new_u32_vec(n: usize) -> Vec<u32> {
   // 疑似乱数生成
@@ -19,3 +23,252 @@ pub fn is_sorted_ascending<T: Ord>(x: &[T]) -> bool {
 pub fn is_sorted_descending<T: Ord>(x: &[T]) -> bool {
   x.windows(2).all(|pair| pair[0] >= pair[1])
 }
+
+// comparatorの順に並んでいるか。等しい要素が隣り合うのは構わない
+pub fn is_sorted_by<T, F>(x: &[T], comparator: &F) -> bool
+where
+  F: Fn(&T, &T) -> Ordering,
+{
+  x.windows(2)
+    .all(|pair| comparator(&pair[0], &pair[1]) != Ordering::Greater)
+}
+
+pub fn is_sorted_by_key<T, K, F>(x: &[T], key: &F) -> bool
+where
+  K: Ord,
+  F: Fn(&T) -> K,
+{
+  is_sorted_by(x, &|a, b| key(a).cmp(&key(b)))
+}
+
+// 列がどれだけ順番から外れているかの診断結果
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Sortedness {
+  // 最初に順番が逆になっている隣り合った位置の組 (i, i + 1)。並んでいればNone
+  pub first_violation: Option<(usize, usize)>,
+  // i < jでx[i]がx[j]より後に来るべき組の数。並んでいれば0、逆順ならn(n-1)/2
+  pub inversions: u64,
+}
+
+impl Sortedness {
+  pub fn is_sorted(&self) -> bool {
+    self.first_violation.is_none()
+  }
+}
+
+pub fn sortedness<T: Ord>(x: &[T], order: &SortOrder) -> Sortedness {
+  match *order {
+    SortOrder::Ascending => sortedness_by(x, &|a, b| a.cmp(b)),
+    SortOrder::Descending => sortedness_by(x, &|a, b| b.cmp(a)),
+  }
+}
+
+// 転倒数はマージソートの途中で数えるのでO(n log n)。xそのものは変更しない
+pub fn sortedness_by<T, F>(x: &[T], comparator: &F) -> Sortedness
+where
+  F: Fn(&T, &T) -> Ordering,
+{
+  let first_violation = x
+    .windows(2)
+    .position(|pair| comparator(&pair[0], &pair[1]) == Ordering::Greater)
+    .map(|i| (i, i + 1));
+  let inversions = if first_violation.is_some() {
+    let mut index: Vec<usize> = (0..x.len()).collect();
+    let mut buf = Vec::with_capacity(x.len());
+    count_inversions(x, &mut index, &mut buf, comparator)
+  } else {
+    0
+  };
+  Sortedness {
+    first_violation,
+    inversions,
+  }
+}
+
+// indexをxの値でマージソートしながら、右半分の要素が左半分に残っている要素を追い越した数を足していく
+fn count_inversions<T, F>(x: &[T], index: &mut [usize], buf: &mut Vec<usize>, comparator: &F) -> u64
+where
+  F: Fn(&T, &T) -> Ordering,
+{
+  if index.len() <= 1 {
+    return 0;
+  }
+  let mid = index.len() / 2;
+  let mut inversions = count_inversions(x, &mut index[..mid], buf, comparator)
+    + count_inversions(x, &mut index[mid..], buf, comparator);
+
+  buf.clear();
+  let (left, right) = index.split_at(mid);
+  let (mut i, mut j) = (0, 0);
+  while i < left.len() && j < right.len() {
+    if comparator(&x[right[j]], &x[left[i]]) == Ordering::Less {
+      inversions += (left.len() - i) as u64;
+      buf.push(right[j]);
+      j += 1;
+    } else {
+      buf.push(left[i]);
+      i += 1;
+    }
+  }
+  buf.extend_from_slice(&left[i..]);
+  buf.extend_from_slice(&right[j..]);
+  index.copy_from_slice(buf);
+  inversions
+}
+
+// permutation[i]番目にある要素がi番目に来るようにxをその場で並べ替える。
+// permutationは0..x.len()の並べ替えでなければならない。argsortの結果をそのまま渡せる
+pub fn apply_permutation<T>(x: &mut [T], permutation: &[usize]) {
+  assert_eq!(x.len(), permutation.len(), "permutation length mismatch");
+  for (i, index) in permutation_swaps(permutation).into_iter().enumerate() {
+    x.swap(i, index);
+  }
+}
+
+// permutationを、i = 0, 1, ...の順にx.swap(i, swaps[i])すれば適用できるswapの列に変換する。
+// 一度求めておけば、同じ並べ替えを複数の列に適用できる
+pub fn permutation_swaps(permutation: &[usize]) -> Vec<usize> {
+  check_permutation(permutation);
+  let mut order = permutation.to_vec();
+  // 既にswapで移動済みの位置は移動先をたどって現在の位置を求める
+  for i in 0..order.len() {
+    let mut index = order[i];
+    while index < i {
+      index = order[index];
+    }
+    order[i] = index;
+  }
+  order
+}
+
+// 範囲外や重複した添字があると移動先をたどるループが終わらないので、先に調べておく
+fn check_permutation(permutation: &[usize]) {
+  let mut seen = vec![false; permutation.len()];
+  for &index in permutation {
+    assert!(
+      index < seen.len() && !seen[index],
+      "invalid permutation index: {}",
+      index
+    );
+    seen[index] = true;
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::{
+    apply_permutation, is_sorted_by, is_sorted_by_key, permutation_swaps, sortedness,
+    sortedness_by, Sortedness,
+  };
+  use crate::utils::new_u32_vec;
+  use crate::SortOrder::*;
+
+  #[test]
+  fn apply_permutation_reorders() {
+    let mut x = vec!["a", "b", "c", "d", "e"];
+    apply_permutation(&mut x, &[3, 0, 4, 1, 2]);
+    assert_eq!(x, vec!["d", "a", "e", "b", "c"]);
+
+    let mut x: Vec<u32> = vec![];
+    apply_permutation(&mut x, &[]);
+    assert_eq!(x, vec![]);
+  }
+
+  #[test]
+  fn permutation_swaps_same_as_apply() {
+    let permutation = [3, 0, 4, 1, 2];
+    let swaps = permutation_swaps(&permutation);
+    let mut x = vec!["a", "b", "c", "d", "e"];
+    for (i, &index) in swaps.iter().enumerate() {
+      x.swap(i, index);
+    }
+    let mut y = vec!["a", "b", "c", "d", "e"];
+    apply_permutation(&mut y, &permutation);
+    assert_eq!(x, y);
+  }
+
+  #[test]
+  #[should_panic(expected = "permutation length mismatch")]
+  fn apply_permutation_length_mismatch() {
+    let mut x = vec![1, 2, 3];
+    apply_permutation(&mut x, &[0, 1]);
+  }
+
+  #[test]
+  #[should_panic(expected = "invalid permutation index: 0")]
+  fn apply_permutation_repeated_index() {
+    let mut x = vec![1, 2, 3];
+    apply_permutation(&mut x, &[0, 0, 1]);
+  }
+
+  #[test]
+  #[should_panic(expected = "invalid permutation index: 3")]
+  fn apply_permutation_index_out_of_range() {
+    let mut x = vec![1, 2, 3];
+    apply_permutation(&mut x, &[2, 3, 0]);
+  }
+
+  #[test]
+  fn is_sorted_by_comparator_and_key() {
+    let x = vec![(1, "c"), (1, "a"), (2, "b")];
+    assert!(is_sorted_by(&x, &|a: &(i32, &str), b: &(i32, &str)| a
+      .0
+      .cmp(&b.0)));
+    assert!(!is_sorted_by(&x, &|a: &(i32, &str), b: &(i32, &str)| a
+      .1
+      .cmp(b.1)));
+    assert!(is_sorted_by_key(&x, &|v: &(i32, &str)| v.0));
+    assert!(is_sorted_by_key(&["ccc", "bb", "a"], &|s: &&str| {
+      std::cmp::Reverse(s.len())
+    }));
+
+    let empty: [u32; 0] = [];
+    assert!(is_sorted_by(&empty, &|a: &u32, b: &u32| a.cmp(b)));
+    assert!(is_sorted_by_key(&[1], &|v: &i32| *v));
+  }
+
+  #[test]
+  fn sortedness_reports_violation_and_inversions() {
+    assert_eq!(
+      sortedness(&[1, 2, 2, 3], &Ascending),
+      Sortedness {
+        first_violation: None,
+        inversions: 0
+      }
+    );
+    assert!(sortedness::<u32>(&[], &Ascending).is_sorted());
+
+    let report = sortedness(&[1, 3, 2, 5, 4, 0], &Ascending);
+    assert_eq!(report.first_violation, Some((1, 2)));
+    // (1,0) (3,2) (3,0) (2,0) (5,4) (5,0) (4,0)
+    assert_eq!(report.inversions, 7);
+    assert!(!report.is_sorted());
+
+    let report = sortedness(&[5, 4, 3, 2, 1], &Ascending);
+    assert_eq!(report.inversions, 10);
+    assert!(sortedness(&[5, 4, 3, 2, 1], &Descending).is_sorted());
+
+    // 等しい要素の組は転倒に数えない
+    let report = sortedness_by(&[(2, 'a'), (1, 'b'), (2, 'c'), (1, 'd')], &|a, b| {
+      a.0.cmp(&b.0)
+    });
+    assert_eq!(report.first_violation, Some((0, 1)));
+    assert_eq!(report.inversions, 3);
+  }
+
+  #[test]
+  fn sortedness_inversions_same_as_brute_force() {
+    for &len in &[2, 3, 17, 100, 257] {
+      let x: Vec<u32> = new_u32_vec(len).into_iter().map(|v| v % 10).collect();
+      let mut expected = 0;
+      for i in 0..len {
+        for j in i + 1..len {
+          if x[i] > x[j] {
+            expected += 1;
+          }
+        }
+      }
+      assert_eq!(sortedness(&x, &Ascending).inversions, expected);
+    }
+  }
+}
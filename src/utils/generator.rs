@@ -0,0 +1,259 @@
+use rand::distributions::{Distribution, Standard};
+use rand::{Rng, SeedableRng};
+use rand_pcg::Pcg64Mcg;
+
+// テストやベンチマーク用の入力の形。
+// どの形も同じシードと長さからは毎回同じ列を作る
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Shape {
+  // 分布から取った値をそのまま並べる
+  Random,
+  // 昇順に並べ済み
+  Sorted,
+  // 降順に並べ済み
+  Reversed,
+  // 前半が昇順、後半が降順の山型。例: 1 3 5 6 4 2
+  OrganPipe,
+  // 長さperiodの昇順の列を繰り返すのこぎり型。最後の列はperiodより短いことがある
+  Sawtooth { period: usize },
+  // 全ての要素が同じ値
+  AllEqual,
+  // distinct種類の値から、k番目の値をk^-exponentに比例する確率で選ぶ。
+  // exponentが大きいほど少数の値に重複が偏る
+  Zipf { distinct: usize, exponent: f64 },
+  // 昇順に並べた後、ランダムに選んだ2つの位置をswaps回入れ替える
+  NearlySorted { swaps: usize },
+}
+
+pub struct Generator {
+  rng: Pcg64Mcg,
+}
+
+impl Generator {
+  pub fn new(seed: u64) -> Self {
+    Self {
+      rng: Pcg64Mcg::seed_from_u64(seed),
+    }
+  }
+
+  // Standard分布で生成できる型の一様乱数の列
+  pub fn vec<T>(&mut self, n: usize) -> Vec<T>
+  where
+    Standard: Distribution<T>,
+  {
+    self.vec_from(n, &Standard)
+  }
+
+  // 任意の分布から取った乱数の列。値の範囲を絞りたい時はUniformなどを渡す
+  pub fn vec_from<T, D: Distribution<T>>(&mut self, n: usize, distribution: &D) -> Vec<T> {
+    self.rng.sample_iter(distribution).take(n).collect()
+  }
+
+  pub fn shaped<T>(&mut self, n: usize, shape: &Shape) -> Vec<T>
+  where
+    T: Ord + Clone,
+    Standard: Distribution<T>,
+  {
+    self.shaped_from(n, shape, &Standard)
+  }
+
+  // distributionから取った値をshapeの形に並べる
+  pub fn shaped_from<T, D>(&mut self, n: usize, shape: &Shape, distribution: &D) -> Vec<T>
+  where
+    T: Ord + Clone,
+    D: Distribution<T>,
+  {
+    match *shape {
+      Shape::Random => self.vec_from(n, distribution),
+      Shape::Sorted => {
+        let mut x = self.vec_from(n, distribution);
+        x.sort();
+        x
+      }
+      Shape::Reversed => {
+        let mut x = self.vec_from(n, distribution);
+        x.sort_by(|a, b| b.cmp(a));
+        x
+      }
+      Shape::OrganPipe => {
+        let mut x = self.vec_from(n, distribution);
+        x.sort();
+        // 偶数番目を前半に昇順で、奇数番目を後半に降順で置く
+        let (mut front, mut back): (Vec<_>, Vec<_>) =
+          x.into_iter().enumerate().partition(|(i, _)| i % 2 == 0);
+        back.reverse();
+        front.append(&mut back);
+        front.into_iter().map(|(_, v)| v).collect()
+      }
+      Shape::Sawtooth { period } => {
+        assert!(period > 0, "sawtooth period must be positive");
+        let mut x = self.vec_from(n, distribution);
+        x.chunks_mut(period).for_each(|chunk| chunk.sort());
+        x
+      }
+      Shape::AllEqual => match self.vec_from(1, distribution).pop() {
+        Some(v) => vec![v; n],
+        None => vec![],
+      },
+      Shape::Zipf { distinct, exponent } => {
+        assert!(distinct > 0, "zipf needs at least one distinct value");
+        let values = self.vec_from(distinct, distribution);
+        let cdf = zipf_cdf(distinct, exponent);
+        (0..n)
+          .map(|_| {
+            let u: f64 = self.rng.gen();
+            // 丸め誤差でcdfの最後が1.0に届かない場合は最後の値にする
+            let rank = cdf.partition_point(|&p| p <= u).min(distinct - 1);
+            values[rank].clone()
+          })
+          .collect()
+      }
+      Shape::NearlySorted { swaps } => {
+        let mut x = self.vec_from(n, distribution);
+        x.sort();
+        if n > 1 {
+          for _ in 0..swaps {
+            let a = self.rng.gen_range(0, n);
+            let b = self.rng.gen_range(0, n);
+            x.swap(a, b);
+          }
+        }
+        x
+      }
+    }
+  }
+}
+
+// シードと長さと形だけで決まる列を作る
+pub fn generate<T>(seed: u64, n: usize, shape: &Shape) -> Vec<T>
+where
+  T: Ord + Clone,
+  Standard: Distribution<T>,
+{
+  Generator::new(seed).shaped(n, shape)
+}
+
+// 順位1..=distinctの累積確率
+fn zipf_cdf(distinct: usize, exponent: f64) -> Vec<f64> {
+  let weights: Vec<f64> = (1..=distinct).map(|k| (k as f64).powf(-exponent)).collect();
+  let total: f64 = weights.iter().sum();
+  let mut acc = 0.0;
+  weights
+    .iter()
+    .map(|w| {
+      acc += w / total;
+      acc
+    })
+    .collect()
+}
+
+#[cfg(test)]
+mod tests {
+  use super::{generate, Generator, Shape};
+  use crate::utils::{is_sorted_ascending, is_sorted_descending};
+  use rand::distributions::Uniform;
+  use std::collections::HashMap;
+
+  const SHAPES: &[Shape] = &[
+    Shape::Random,
+    Shape::Sorted,
+    Shape::Reversed,
+    Shape::OrganPipe,
+    Shape::Sawtooth { period: 7 },
+    Shape::AllEqual,
+    Shape::Zipf {
+      distinct: 10,
+      exponent: 1.2,
+    },
+    Shape::NearlySorted { swaps: 3 },
+  ];
+
+  #[test]
+  fn shapes_are_deterministic() {
+    for shape in SHAPES {
+      let x: Vec<u32> = generate(42, 1000, shape);
+      let y: Vec<u32> = generate(42, 1000, shape);
+      assert_eq!(x, y, "{:?}", shape);
+      assert_eq!(x.len(), 1000);
+    }
+    let x: Vec<u32> = generate(1, 100, &Shape::Random);
+    let y: Vec<u32> = generate(2, 100, &Shape::Random);
+    assert_ne!(x, y);
+  }
+
+  #[test]
+  fn shapes_empty() {
+    for shape in SHAPES {
+      let x: Vec<u64> = generate(0, 0, shape);
+      assert!(x.is_empty());
+    }
+  }
+
+  #[test]
+  fn shapes_have_expected_order() {
+    let x: Vec<u32> = generate(7, 1001, &Shape::Sorted);
+    assert!(is_sorted_ascending(&x));
+
+    let x: Vec<i64> = generate(7, 1001, &Shape::Reversed);
+    assert!(is_sorted_descending(&x));
+
+    let x: Vec<u32> = generate(7, 1001, &Shape::OrganPipe);
+    let peak = x.len().div_ceil(2);
+    assert!(is_sorted_ascending(&x[..peak]));
+    assert!(is_sorted_descending(&x[peak..]));
+    assert!(x[peak - 1] >= x[peak]);
+
+    let x: Vec<u32> = generate(7, 1001, &Shape::Sawtooth { period: 10 });
+    assert!(x.chunks(10).all(is_sorted_ascending));
+    assert!(!is_sorted_ascending(&x));
+
+    let x: Vec<u8> = generate(7, 1001, &Shape::AllEqual);
+    assert!(x.iter().all(|&v| v == x[0]));
+  }
+
+  #[test]
+  fn nearly_sorted_has_few_displaced_elements() {
+    let x: Vec<u32> = generate(3, 1000, &Shape::NearlySorted { swaps: 5 });
+    let mut sorted = x.clone();
+    sorted.sort();
+    let displaced = x.iter().zip(sorted.iter()).filter(|(a, b)| a != b).count();
+    assert!(displaced > 0);
+    assert!(displaced <= 10);
+
+    let x: Vec<u32> = generate(3, 1000, &Shape::NearlySorted { swaps: 0 });
+    assert!(is_sorted_ascending(&x));
+  }
+
+  #[test]
+  fn zipf_is_skewed() {
+    let shape = Shape::Zipf {
+      distinct: 20,
+      exponent: 1.5,
+    };
+    let x: Vec<u64> = generate(11, 10_000, &shape);
+    let mut counts: HashMap<u64, usize> = HashMap::new();
+    x.iter().for_each(|v| *counts.entry(*v).or_insert(0) += 1);
+    assert!(counts.len() <= 20);
+    let mut counts: Vec<usize> = counts.values().cloned().collect();
+    counts.sort();
+    // 一番多い値は全体のおよそ4割になる
+    let top = *counts.last().unwrap();
+    assert!(top > 3_000 && top < 5_000, "top count: {}", top);
+  }
+
+  #[test]
+  fn generator_with_distribution() {
+    let mut gen = Generator::new(5);
+    let x: Vec<i32> = gen.vec_from(1000, &Uniform::new_inclusive(-3, 3));
+    assert!(x.iter().all(|v| (-3..=3).contains(v)));
+
+    let x: Vec<u16> = gen.shaped_from(1000, &Shape::Reversed, &Uniform::new(0, 10));
+    assert!(is_sorted_descending(&x));
+    assert!(x.iter().all(|&v| v < 10));
+
+    // 同じGeneratorから続けて取った列は別の値になる
+    let a: Vec<u32> = gen.vec(100);
+    let b: Vec<u32> = gen.vec(100);
+    assert_ne!(a, b);
+  }
+}
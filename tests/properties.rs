@@ -0,0 +1,239 @@
+use bitonic_sorter::fourth::ParSorter;
+use bitonic_sorter::SortOrder::{self, *};
+use bitonic_sorter::{first, fourth, iterative, second, third, SortError};
+use proptest::collection::vec;
+use proptest::prelude::*;
+use std::cmp::Ordering;
+use std::fmt::Debug;
+
+// 各ソーターの結果をslice::sortと比べるプロパティテスト。
+// 並べ替え後の列が元の列の並べ替えになっていることと、比較関数の順に並んでいることを別々に確かめる
+
+// 2^0から2^10までの長さの列
+fn power_of_two<T: Debug>(
+  element: impl Strategy<Value = T> + Clone,
+) -> impl Strategy<Value = Vec<T>> {
+  (0_u32..=10).prop_flat_map(move |bits| vec(element.clone(), 1 << bits))
+}
+
+// 2のべき乗に限らない長さの列
+fn any_length<T: Debug>(element: impl Strategy<Value = T>) -> impl Strategy<Value = Vec<T>> {
+  vec(element, 0..1500)
+}
+
+// 値が数種類しかない重複の多い列
+fn duplicates() -> impl Strategy<Value = Vec<u32>> {
+  (1_u32..=4).prop_flat_map(|distinct| vec(0..distinct, 0..1500))
+}
+
+fn order() -> impl Strategy<Value = bool> {
+  any::<bool>()
+}
+
+fn sort_order(ascending: bool) -> SortOrder {
+  if ascending {
+    Ascending
+  } else {
+    Descending
+  }
+}
+
+fn std_sorted<T: Ord + Clone>(x: &[T], ascending: bool) -> Vec<T> {
+  let mut expected = x.to_vec();
+  if ascending {
+    expected.sort();
+  } else {
+    expected.sort_by(|a, b| b.cmp(a));
+  }
+  expected
+}
+
+// 要素の多重集合が一致していればoutputはinputの並べ替え
+fn assert_permutation<T: Ord + Clone + Debug>(
+  input: &[T],
+  output: &[T],
+) -> Result<(), TestCaseError> {
+  let mut a = input.to_vec();
+  let mut b = output.to_vec();
+  a.sort();
+  b.sort();
+  prop_assert_eq!(a, b, "output is not a permutation of the input");
+  Ok(())
+}
+
+fn assert_sorted_by<T: Debug, F>(x: &[T], comparator: F) -> Result<(), TestCaseError>
+where
+  F: Fn(&T, &T) -> Ordering,
+{
+  for pair in x.windows(2) {
+    prop_assert_ne!(
+      comparator(&pair[0], &pair[1]),
+      Ordering::Greater,
+      "out of order: {:?}",
+      pair
+    );
+  }
+  Ok(())
+}
+
+// 並べ替えの確認と、標準ライブラリの結果との比較をまとめて行う
+fn check_against_std<T: Ord + Clone + Debug>(
+  input: &[T],
+  output: &[T],
+  ascending: bool,
+) -> Result<(), TestCaseError> {
+  assert_permutation(input, output)?;
+  prop_assert_eq!(output.to_vec(), std_sorted(input, ascending));
+  Ok(())
+}
+
+// 比較関数で使う要素。keyだけで比べるので、等しい要素の順番はソーターごとに違ってよい
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+struct Item {
+  key: u8,
+  payload: u32,
+}
+
+fn items() -> impl Strategy<Value = Vec<Item>> {
+  any_length((0_u8..16, any::<u32>()).prop_map(|(key, payload)| Item { key, payload }))
+}
+
+fn by_key_desc(a: &Item, b: &Item) -> Ordering {
+  b.key.cmp(&a.key)
+}
+
+proptest! {
+  #[test]
+  fn first_same_as_std(x in power_of_two(any::<u32>()), ascending in order()) {
+    let mut y = x.clone();
+    first::sort(&mut y, ascending);
+    check_against_std(&x, &y, ascending)?;
+  }
+
+  #[test]
+  fn first_duplicates(bits in 0_u32..=10, distinct in 1_u32..=4, ascending in order()) {
+    let x: Vec<u32> = (0..1_u32 << bits).map(|i| i.wrapping_mul(2_654_435_761) % distinct).collect();
+    let mut y = x.clone();
+    first::sort(&mut y, ascending);
+    check_against_std(&x, &y, ascending)?;
+  }
+
+  #[test]
+  fn second_same_as_std(x in power_of_two(any::<i64>()), ascending in order()) {
+    let mut y = x.clone();
+    prop_assert_eq!(second::sort(&mut y, &sort_order(ascending)), Ok(()));
+    check_against_std(&x, &y, ascending)?;
+  }
+
+  #[test]
+  fn second_strings(x in power_of_two("[a-c]{0,3}"), ascending in order()) {
+    let mut y = x.clone();
+    prop_assert_eq!(second::sort(&mut y, &sort_order(ascending)), Ok(()));
+    check_against_std(&x, &y, ascending)?;
+  }
+
+  #[test]
+  fn second_rejects_other_lengths(x in any_length(any::<u32>())) {
+    prop_assume!(!x.len().is_power_of_two());
+    let mut y = x.clone();
+    prop_assert_eq!(second::sort(&mut y, &Ascending), Err(SortError::not_power_of_two(x.len())));
+    prop_assert_eq!(y, x);
+  }
+
+  #[test]
+  fn third_same_as_std(x in any_length(any::<u32>()), ascending in order()) {
+    let mut y = x.clone();
+    prop_assert_eq!(third::sort(&mut y, &sort_order(ascending)), Ok(()));
+    check_against_std(&x, &y, ascending)?;
+  }
+
+  #[test]
+  fn third_power_of_two(x in power_of_two(any::<u16>()), ascending in order()) {
+    let mut y = x.clone();
+    prop_assert_eq!(third::sort(&mut y, &sort_order(ascending)), Ok(()));
+    check_against_std(&x, &y, ascending)?;
+  }
+
+  #[test]
+  fn third_duplicates(x in duplicates(), ascending in order()) {
+    let mut y = x.clone();
+    prop_assert_eq!(third::sort(&mut y, &sort_order(ascending)), Ok(()));
+    check_against_std(&x, &y, ascending)?;
+  }
+
+  #[test]
+  fn third_custom_comparator(x in items()) {
+    let mut y = x.clone();
+    prop_assert_eq!(third::sort_by(&mut y, &by_key_desc), Ok(()));
+    assert_permutation(&x, &y)?;
+    assert_sorted_by(&y, by_key_desc)?;
+  }
+
+  #[test]
+  fn fourth_same_as_std(x in any_length(any::<u32>()), ascending in order()) {
+    let mut y = x.clone();
+    prop_assert_eq!(fourth::sort(&mut y, &sort_order(ascending)), Ok(()));
+    check_against_std(&x, &y, ascending)?;
+  }
+
+  #[test]
+  fn fourth_power_of_two(x in power_of_two(any::<u16>()), ascending in order()) {
+    let mut y = x.clone();
+    prop_assert_eq!(fourth::sort(&mut y, &sort_order(ascending)), Ok(()));
+    check_against_std(&x, &y, ascending)?;
+  }
+
+  #[test]
+  fn fourth_duplicates(x in duplicates(), ascending in order()) {
+    let mut y = x.clone();
+    prop_assert_eq!(fourth::sort(&mut y, &sort_order(ascending)), Ok(()));
+    check_against_std(&x, &y, ascending)?;
+  }
+
+  #[test]
+  fn fourth_custom_comparator(x in items()) {
+    let mut y = x.clone();
+    prop_assert_eq!(fourth::sort_by(&mut y, &by_key_desc), Ok(()));
+    assert_permutation(&x, &y)?;
+    assert_sorted_by(&y, by_key_desc)?;
+  }
+
+  // 閾値を小さくして、短い列でも並列のcompare_and_swapを通す
+  #[test]
+  fn fourth_small_threshold(x in any_length(any::<u32>()), threshold in 1_usize..64, ascending in order()) {
+    let mut y = x.clone();
+    let sorter = ParSorter::new().threshold(threshold);
+    prop_assert_eq!(sorter.sort(&mut y, &sort_order(ascending)), Ok(()));
+    check_against_std(&x, &y, ascending)?;
+  }
+
+  #[test]
+  fn iterative_same_as_std(x in any_length(any::<u32>()), ascending in order()) {
+    let mut y = x.clone();
+    prop_assert_eq!(iterative::sort(&mut y, &sort_order(ascending)), Ok(()));
+    check_against_std(&x, &y, ascending)?;
+  }
+
+  #[test]
+  fn iterative_custom_comparator(x in items()) {
+    let mut y = x.clone();
+    prop_assert_eq!(iterative::sort_by(&mut y, &by_key_desc), Ok(()));
+    assert_permutation(&x, &y)?;
+    assert_sorted_by(&y, by_key_desc)?;
+  }
+
+  // 安定ソートは比較関数で等しい要素の順番まで標準ライブラリと一致する
+  #[test]
+  fn stable_sort_same_as_std(x in items()) {
+    let mut expected = x.clone();
+    expected.sort_by(by_key_desc);
+
+    let mut y = x.clone();
+    prop_assert_eq!(third::stable_sort_by(&mut y, &by_key_desc), Ok(()));
+    prop_assert_eq!(&y, &expected);
+
+    let mut y = x.clone();
+    prop_assert_eq!(fourth::stable_sort_by(&mut y, &by_key_desc), Ok(()));
+    prop_assert_eq!(&y, &expected);
+  }
+}